@@ -1,14 +1,62 @@
+mod alternatives;
+mod bom;
+mod clash;
+mod constraints;
+mod diagnostics;
+mod duct;
+mod expansion;
 mod grid;
+mod hangers;
+mod hydraulics;
+mod layers;
 mod octtree;
+mod pareto;
+mod penetration;
+mod postprocess;
+mod replan;
+mod revision;
+mod router;
+mod rules;
+mod search;
+mod spool;
+mod steiner;
+mod terminal;
+mod tray;
+mod verify;
+mod zones;
 
 use std::{marker::PhantomData, path::Path};
 
+pub use alternatives::*;
+pub use bom::*;
+pub use clash::*;
+pub use constraints::*;
+pub use diagnostics::*;
+pub use duct::*;
+pub use expansion::*;
 pub use grid::*;
+pub use hangers::*;
+pub use hydraulics::*;
+pub use layers::*;
 pub use octtree::*;
+pub use pareto::*;
+pub use penetration::*;
+pub use postprocess::*;
+pub use replan::*;
+pub use revision::*;
+pub use router::*;
+pub use rules::*;
+pub use search::*;
+pub use spool::*;
+pub use steiner::*;
+pub use terminal::*;
+pub use tray::*;
+pub use verify::*;
+pub use zones::*;
 
 use crate::{math::vector::Vector3, scene::Bounds, utility::GenError};
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct PathfindingPath {
     pub points: Vec<Vector3>,
 }
@@ -22,9 +70,56 @@ impl PathfindingPath {
             .collect();
         Ok(Self { points })
     }
+
+    // Whether any segment passes through `region`.
+    pub fn crosses(&self, region: &Bounds) -> bool {
+        self.points
+            .windows(2)
+            .any(|pair| region.intersect_segment(pair[0], pair[1]).is_some())
+    }
+
+    pub fn length(&self) -> f32 {
+        self.points
+            .windows(2)
+            .map(|pair| (pair[1] - pair[0]).length())
+            .sum()
+    }
+
+    // Distance along the path at which every point lies.
+    pub fn offsets(&self) -> Vec<f32> {
+        let mut offsets = Vec::with_capacity(self.points.len());
+        let mut total = 0.0;
+        for (i, point) in self.points.iter().enumerate() {
+            if i > 0 {
+                total += (*point - self.points[i - 1]).length();
+            }
+            offsets.push(total);
+        }
+        offsets
+    }
+
+    // Segment index and position at `distance` along the path, clamped to its ends.
+    pub fn point_at(&self, distance: f32) -> (usize, Vector3) {
+        let mut remaining = distance.max(0.0);
+        for (segment, pair) in self.points.windows(2).enumerate() {
+            let length = (pair[1] - pair[0]).length();
+            if remaining <= length && length > 0.0 {
+                return (
+                    segment,
+                    pair[0] + (remaining / length) * (pair[1] - pair[0]),
+                );
+            }
+            remaining -= length;
+        }
+        let last = self.points.len().saturating_sub(2);
+        (last, *self.points.last().unwrap_or(&Vector3::zero()))
+    }
 }
 
 pub trait VoxelStrategy {
+    // Whether every cell blocked by an obstacle lies within the obstacle's bounds.
+    const LOCAL: bool = true;
+
     fn is_valid(rhs: &Bounds, lhs: &Bounds) -> bool;
 }
 
@@ -49,6 +144,8 @@ pub struct Neg<S: VoxelStrategy> {
 }
 
 impl<S: VoxelStrategy> VoxelStrategy for Neg<S> {
+    const LOCAL: bool = false;
+
     fn is_valid(rhs: &Bounds, lhs: &Bounds) -> bool {
         !S::is_valid(rhs, lhs)
     }
@@ -0,0 +1,258 @@
+use std::collections::HashSet;
+
+use serde::Serialize;
+
+use crate::{math::vector::Vector3, scene::Scene, utility::GenError};
+
+use super::{
+    count_bends, direction_between, find_penetrations, Cell, CostModel, Grid, PathfindingPath,
+    Penetrating, PenetrationPolicy, SearchParams,
+};
+
+#[derive(Debug, Clone, Copy)]
+pub struct AlternativeConfig {
+    pub count: usize,
+    // Routes closer than this to an accepted route count as running along it.
+    pub corridor: f32,
+    // Largest share of a new route that may run along an accepted one.
+    pub max_overlap: f32,
+    // Multiplier on the cost of steps inside accepted corridors while searching for detours.
+    pub overlap_penalty: f32,
+    pub max_spur_searches: usize,
+    pub search: SearchParams,
+}
+
+impl Default for AlternativeConfig {
+    fn default() -> Self {
+        Self {
+            count: 3,
+            corridor: 0.5,
+            max_overlap: 0.6,
+            overlap_penalty: 2.0,
+            max_spur_searches: 200,
+            search: SearchParams::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct RouteMetrics {
+    pub length: f32,
+    pub bends: usize,
+    pub penetrations: usize,
+    pub cost: f32,
+}
+
+#[derive(Debug, Clone)]
+pub struct AlternativeRoute {
+    pub cells: Vec<Cell>,
+    pub path: PathfindingPath,
+    pub metrics: RouteMetrics,
+}
+
+#[derive(Serialize)]
+struct RouteExport<'a> {
+    points: Vec<[f32; 3]>,
+    #[serde(flatten)]
+    metrics: &'a RouteMetrics,
+}
+
+// Serialises the routes, cheapest first, for display side by side.
+pub fn alternatives_to_json(routes: &[AlternativeRoute]) -> GenError<String> {
+    let routes: Vec<RouteExport> = routes
+        .iter()
+        .map(|route| RouteExport {
+            points: route.path.points.iter().map(|p| [p.x, p.y, p.z]).collect(),
+            metrics: &route.metrics,
+        })
+        .collect();
+    Ok(serde_json::to_string_pretty(&routes)?)
+}
+
+// Search cost for a spur: the root of the route and the edges already taken from the spur
+// cell are closed, and accepted corridors are made more expensive.
+struct Detour<'a, C: CostModel + ?Sized> {
+    inner: &'a C,
+    nodes: HashSet<Cell>,
+    edges: HashSet<(Cell, Cell)>,
+    corridors: &'a HashSet<Cell>,
+    penalty: f32,
+}
+
+impl<C: CostModel + ?Sized> CostModel for Detour<'_, C> {
+    fn step_cost(&self, grid: &Grid, from: Cell, to: Cell) -> Option<f32> {
+        if self.nodes.contains(&to) || self.edges.contains(&(from, to)) {
+            return None;
+        }
+        let cost = self.inner.step_cost(grid, from, to)?;
+        if self.corridors.contains(&to) {
+            Some(cost * (1.0 + self.penalty))
+        } else {
+            Some(cost)
+        }
+    }
+
+    fn lower_bound(&self, grid: &Grid) -> f32 {
+        self.inner.lower_bound(grid)
+    }
+}
+
+// Where spur searches branch off: the start, every bend and the middle of every straight.
+fn spur_indices(cells: &[Cell]) -> Vec<usize> {
+    let mut bends = vec![0];
+    for i in 1..cells.len().saturating_sub(1) {
+        if direction_between(cells[i - 1], cells[i]) != direction_between(cells[i], cells[i + 1]) {
+            bends.push(i);
+        }
+    }
+    bends.push(cells.len() - 1);
+    let mut indices = vec![];
+    for pair in bends.windows(2) {
+        indices.push(pair[0]);
+        if pair[1] - pair[0] > 2 {
+            indices.push((pair[0] + pair[1]) / 2);
+        }
+    }
+    indices
+}
+
+impl Grid {
+    fn route_cost<C: CostModel + ?Sized>(
+        &self,
+        cells: &[Cell],
+        cost: &C,
+        params: &SearchParams,
+    ) -> Option<f32> {
+        let mut total = count_bends(cells) as f32 * params.bend_cost;
+        for pair in cells.windows(2) {
+            total += cost.step_cost(self, pair[0], pair[1])?;
+        }
+        Some(total)
+    }
+
+    // Yen's k-shortest paths, restricted to routes that differ enough from every route
+    // already accepted. Returns the routes with their costs, cheapest first.
+    pub fn alternative_cells<C: CostModel + ?Sized>(
+        &self,
+        start: Cell,
+        goal: Cell,
+        cost: &C,
+        config: &AlternativeConfig,
+    ) -> Vec<(Vec<Cell>, f32)> {
+        let params = &config.search;
+        let Some(first) = self.search(&[start], &[goal], cost, params) else {
+            return vec![];
+        };
+        let reach = (config.corridor / self.cell_size()).round() as usize;
+        let corridor = |cells: &[Cell]| -> HashSet<Cell> {
+            cells
+                .iter()
+                .flat_map(|&cell| self.cells_within(cell, reach))
+                .collect()
+        };
+        let overlap = |cells: &[Cell], corridor: &HashSet<Cell>| {
+            cells.iter().filter(|cell| corridor.contains(cell)).count() as f32 / cells.len() as f32
+        };
+
+        let first_cost = self
+            .route_cost(&first, cost, params)
+            .unwrap_or(f32::INFINITY);
+        let mut corridors = vec![corridor(&first)];
+        let mut all_corridors: HashSet<Cell> = corridors[0].clone();
+        let mut accepted = vec![(first, first_cost)];
+        let mut candidates: Vec<(Vec<Cell>, f32)> = vec![];
+        let mut seen: HashSet<Vec<Cell>> = HashSet::new();
+        let mut searches = 0;
+
+        while accepted.len() < config.count {
+            let last = accepted.last().unwrap().0.clone();
+            for i in spur_indices(&last) {
+                if searches >= config.max_spur_searches {
+                    break;
+                }
+                let root = &last[..=i];
+                let edges = accepted
+                    .iter()
+                    .filter(|(cells, _)| cells.len() > i + 1 && cells[..=i] == *root)
+                    .map(|(cells, _)| (cells[i], cells[i + 1]))
+                    .collect();
+                let detour = Detour {
+                    inner: cost,
+                    nodes: root[..i].iter().copied().collect(),
+                    edges,
+                    corridors: &all_corridors,
+                    penalty: config.overlap_penalty,
+                };
+                searches += 1;
+                let Some(spur) = self.search(&[last[i]], &[goal], &detour, params) else {
+                    continue;
+                };
+                let mut cells = root[..i].to_vec();
+                cells.extend(spur);
+                if !seen.insert(cells.clone()) {
+                    continue;
+                }
+                if let Some(total) = self.route_cost(&cells, cost, params) {
+                    candidates.push((cells, total));
+                }
+            }
+
+            // Candidates too close to an accepted route will never qualify.
+            candidates.retain(|(cells, _)| {
+                corridors
+                    .iter()
+                    .all(|corridor| overlap(cells, corridor) <= config.max_overlap)
+            });
+            let Some(best) = candidates
+                .iter()
+                .enumerate()
+                .min_by(|a, b| a.1 .1.total_cmp(&b.1 .1))
+                .map(|(i, _)| i)
+            else {
+                break;
+            };
+            let chosen = candidates.swap_remove(best);
+            let chosen_corridor = corridor(&chosen.0);
+            all_corridors.extend(chosen_corridor.iter().copied());
+            corridors.push(chosen_corridor);
+            accepted.push(chosen);
+        }
+        // Later rounds can find cheaper routes than earlier ones.
+        accepted.sort_by(|a, b| a.1.total_cmp(&b.1));
+        accepted
+    }
+}
+
+// Up to `config.count` distinct routes between two points that may pass through
+// penetrable elements, each with its length, bends and penetrations.
+pub fn alternative_routes(
+    grid: &Grid,
+    scene: &Scene,
+    start: Vector3,
+    goal: Vector3,
+    diameter: f32,
+    policy: &PenetrationPolicy,
+    config: &AlternativeConfig,
+) -> Vec<AlternativeRoute> {
+    let (Some(start), Some(goal)) = (grid.cell_at(start), grid.cell_at(goal)) else {
+        return vec![];
+    };
+    let cost = Penetrating { scene, policy };
+    grid.alternative_cells(start, goal, &cost, config)
+        .into_iter()
+        .map(|(cells, cost)| {
+            let path = grid.cells_to_path(&cells).merge_collinear(1e-4);
+            let metrics = RouteMetrics {
+                length: path.length(),
+                bends: count_bends(&cells),
+                penetrations: find_penetrations(scene, &path, diameter, policy).len(),
+                cost,
+            };
+            AlternativeRoute {
+                cells,
+                path,
+                metrics,
+            }
+        })
+        .collect()
+}
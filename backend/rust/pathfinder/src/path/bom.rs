@@ -0,0 +1,255 @@
+use std::{fmt::Write as _, path::Path};
+
+use serde::Serialize;
+
+use crate::{math::vector::Vector3, scene::Scene, utility::GenError};
+
+use super::{find_penetrations, PathfindingPath, PenetrationPolicy, PipeNetwork};
+
+#[derive(Debug, Clone)]
+pub struct PipeSpec {
+    pub material: String,
+    // Outside diameter in metres.
+    pub diameter: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum BomItemKind {
+    Pipe,
+    Elbow,
+    Tee,
+    Cross,
+    Reducer,
+    Sleeve,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BomItem {
+    pub kind: BomItemKind,
+    pub material: String,
+    pub diameter: f32,
+    // Smaller outlet of reducers, reducing tees and crosses.
+    pub reduced_diameter: Option<f32>,
+    // Deflection of elbows in degrees.
+    pub angle: Option<f32>,
+    pub quantity: usize,
+    // Total length of pipe and sleeve items.
+    pub length: Option<f32>,
+}
+
+impl BomItem {
+    fn matches(&self, other: &BomItem) -> bool {
+        self.kind == other.kind
+            && self.material == other.material
+            && self.diameter == other.diameter
+            && self.reduced_diameter == other.reduced_diameter
+            && self.angle == other.angle
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Bom {
+    pub items: Vec<BomItem>,
+}
+
+// Sizes are grouped to the millimetre and angles to the degree.
+fn round_size(size: f32) -> f32 {
+    (size * 1000.0).round() / 1000.0
+}
+
+fn deflection(incoming: Vector3, outgoing: Vector3) -> f32 {
+    (incoming.norm() * outgoing.norm())
+        .clamp(-1.0, 1.0)
+        .acos()
+        .to_degrees()
+        .round()
+}
+
+impl Bom {
+    pub fn from_path(
+        scene: &Scene,
+        path: &PathfindingPath,
+        spec: &PipeSpec,
+        policy: &PenetrationPolicy,
+    ) -> Bom {
+        let mut bom = Bom::default();
+        bom.add_run(scene, path, spec, policy);
+        bom
+    }
+
+    // Bill of materials for a branched network, with `specs` giving the pipe of every edge.
+    pub fn from_network(
+        scene: &Scene,
+        network: &PipeNetwork,
+        specs: &[PipeSpec],
+        policy: &PenetrationPolicy,
+    ) -> GenError<Bom> {
+        if specs.len() != network.edges.len() {
+            return Err(format!(
+                "{} pipe specs given for {} network edges",
+                specs.len(),
+                network.edges.len()
+            )
+            .into());
+        }
+        let mut bom = Bom::default();
+        for (edge, spec) in network.edges.iter().zip(specs) {
+            bom.add_run(scene, &edge.path, spec, policy);
+        }
+
+        for n in 0..network.nodes.len() {
+            // Every edge meeting at the node, with the direction pointing away from it.
+            let incident: Vec<(&PipeSpec, Vector3)> = network
+                .edges
+                .iter()
+                .zip(specs)
+                .filter_map(|(edge, spec)| {
+                    let points = &edge.path.points;
+                    if points.len() < 2 {
+                        return None;
+                    }
+                    let outward = if edge.from == n {
+                        points[1] - points[0]
+                    } else if edge.to == n {
+                        points[points.len() - 2] - points[points.len() - 1]
+                    } else {
+                        return None;
+                    };
+                    Some((spec, outward))
+                })
+                .collect();
+            let Some(&(main, _)) = incident
+                .iter()
+                .max_by(|a, b| a.0.diameter.total_cmp(&b.0.diameter))
+            else {
+                continue;
+            };
+            let smallest = incident
+                .iter()
+                .map(|(spec, _)| spec.diameter)
+                .fold(main.diameter, f32::min);
+            let reduced = (round_size(smallest) < round_size(main.diameter)).then_some(smallest);
+            // The fitting follows from the pipes that meet, whatever the node was placed for.
+            match incident.len() {
+                0 | 1 => {}
+                2 => {
+                    let angle = deflection(-incident[0].1, incident[1].1);
+                    if angle >= 1.0 {
+                        bom.add(BomItemKind::Elbow, main, None, Some(angle), 1, None);
+                    }
+                    if reduced.is_some() {
+                        bom.add(BomItemKind::Reducer, main, reduced, None, 1, None);
+                    }
+                }
+                3 => bom.add(BomItemKind::Tee, main, reduced, None, 1, None),
+                // Beyond four outlets a cross is extended with one tee per extra branch.
+                count => {
+                    bom.add(BomItemKind::Cross, main, reduced, None, 1, None);
+                    if count > 4 {
+                        bom.add(BomItemKind::Tee, main, reduced, None, count - 4, None);
+                    }
+                }
+            }
+        }
+        Ok(bom)
+    }
+
+    fn add_run(
+        &mut self,
+        scene: &Scene,
+        path: &PathfindingPath,
+        spec: &PipeSpec,
+        policy: &PenetrationPolicy,
+    ) {
+        let path = path.merge_collinear(1e-4);
+        let length = path.length();
+        if length > 0.0 {
+            let pieces = path.points.len() - 1;
+            self.add(BomItemKind::Pipe, spec, None, None, pieces, Some(length));
+        }
+        for w in path.points.windows(3) {
+            let angle = deflection(w[1] - w[0], w[2] - w[1]);
+            if angle >= 1.0 {
+                self.add(BomItemKind::Elbow, spec, None, Some(angle), 1, None);
+            }
+        }
+        for penetration in find_penetrations(scene, &path, spec.diameter, policy) {
+            let sleeve = PipeSpec {
+                material: spec.material.clone(),
+                diameter: penetration.sleeve_diameter,
+            };
+            let length = Some(penetration.sleeve_length);
+            self.add(BomItemKind::Sleeve, &sleeve, None, None, 1, length);
+        }
+    }
+
+    fn add(
+        &mut self,
+        kind: BomItemKind,
+        spec: &PipeSpec,
+        reduced_diameter: Option<f32>,
+        angle: Option<f32>,
+        quantity: usize,
+        length: Option<f32>,
+    ) {
+        let item = BomItem {
+            kind,
+            material: spec.material.clone(),
+            diameter: round_size(spec.diameter),
+            reduced_diameter: reduced_diameter.map(round_size),
+            angle,
+            quantity,
+            length,
+        };
+        match self
+            .items
+            .iter_mut()
+            .find(|existing| existing.matches(&item))
+        {
+            Some(existing) => {
+                existing.quantity += quantity;
+                existing.length = match (existing.length, length) {
+                    (Some(a), Some(b)) => Some(a + b),
+                    (a, b) => a.or(b),
+                };
+            }
+            None => self.items.push(item),
+        }
+    }
+
+    pub fn to_json(&self) -> GenError<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    pub fn to_csv(&self) -> String {
+        let mut csv =
+            String::from("kind,material,diameter,reduced_diameter,angle,quantity,length\n");
+        let optional = |value: Option<f32>| value.map(|v| v.to_string()).unwrap_or_default();
+        for item in self.items.iter() {
+            // Material names may contain commas, so they are always quoted.
+            let _ = writeln!(
+                csv,
+                "{:?},\"{}\",{},{},{},{},{}",
+                item.kind,
+                item.material.replace('"', "\"\""),
+                item.diameter,
+                optional(item.reduced_diameter),
+                optional(item.angle),
+                item.quantity,
+                optional(item.length),
+            );
+        }
+        csv
+    }
+
+    pub fn save_json(&self, path: &Path) -> GenError<()> {
+        std::fs::write(path, self.to_json()?)?;
+        Ok(())
+    }
+
+    pub fn save_csv(&self, path: &Path) -> GenError<()> {
+        std::fs::write(path, self.to_csv())?;
+        Ok(())
+    }
+}
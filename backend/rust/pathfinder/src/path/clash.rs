@@ -0,0 +1,243 @@
+use std::collections::{BTreeMap, HashMap};
+
+use crate::{math::vector::Vector3, scene::Scene};
+
+use super::PathfindingPath;
+
+#[derive(Debug, Clone)]
+pub struct ClashPipe {
+    pub id: String,
+    // System or service class, used to look up tolerances.
+    pub class: String,
+    pub diameter: f32,
+    pub path: PathfindingPath,
+}
+
+#[derive(Debug, Clone)]
+pub struct ClashConfig {
+    // Required gap between surfaces for a pair of classes, looked up in either order.
+    pub tolerances: HashMap<(String, String), f32>,
+    pub default_tolerance: f32,
+    // Parallel pipes whose centrelines are closer than this are the same pipe modelled twice.
+    pub duplicate_tolerance: f32,
+}
+
+impl Default for ClashConfig {
+    fn default() -> Self {
+        Self {
+            tolerances: HashMap::new(),
+            default_tolerance: 0.05,
+            duplicate_tolerance: 0.01,
+        }
+    }
+}
+
+impl ClashConfig {
+    pub fn tolerance(&self, a: &str, b: &str) -> f32 {
+        let key = |a: &str, b: &str| (a.to_string(), b.to_string());
+        self.tolerances
+            .get(&key(a, b))
+            .or_else(|| self.tolerances.get(&key(b, a)))
+            .copied()
+            .unwrap_or(self.default_tolerance)
+    }
+}
+
+// Ordered from most to least severe.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ClashKind {
+    Duplicate,
+    Hard,
+    Clearance,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ClashItem {
+    // Index into the pipes passed to `detect_clashes`.
+    Pipe(usize),
+    // Index into `Scene::obstacles`.
+    Element(usize),
+}
+
+#[derive(Debug, Clone)]
+pub struct Clash {
+    pub kind: ClashKind,
+    pub segment_a: usize,
+    // Segment of the second pipe, `None` for a building element.
+    pub segment_b: Option<usize>,
+    pub position: Vector3,
+    // Gap between the surfaces, negative when they overlap.
+    pub distance: f32,
+}
+
+#[derive(Debug, Clone)]
+pub struct ClashGroup {
+    pub a: ClashItem,
+    pub b: ClashItem,
+    // Most severe kind among `clashes`.
+    pub kind: ClashKind,
+    pub clashes: Vec<Clash>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ClashReport {
+    pub groups: Vec<ClashGroup>,
+}
+
+impl ClashReport {
+    pub fn count(&self, kind: ClashKind) -> usize {
+        self.groups
+            .iter()
+            .filter(|group| group.kind == kind)
+            .count()
+    }
+
+    pub fn is_clean(&self) -> bool {
+        self.groups.is_empty()
+    }
+}
+
+// Closest points between segments `p1`-`q1` and `p2`-`q2`, as the distance and the parameter
+// along each segment.
+pub(crate) fn segment_segment(
+    p1: Vector3,
+    q1: Vector3,
+    p2: Vector3,
+    q2: Vector3,
+) -> (f32, f32, f32) {
+    let (d1, d2, r) = (q1 - p1, q2 - p2, p1 - p2);
+    let (a, e, f) = (d1 * d1, d2 * d2, d2 * r);
+    let eps = 1e-12;
+    let (s, t) = if a <= eps && e <= eps {
+        (0.0, 0.0)
+    } else if a <= eps {
+        (0.0, (f / e).clamp(0.0, 1.0))
+    } else {
+        let c = d1 * r;
+        if e <= eps {
+            ((-c / a).clamp(0.0, 1.0), 0.0)
+        } else {
+            let b = d1 * d2;
+            let denom = a * e - b * b;
+            let s = if denom > eps {
+                ((b * f - c * e) / denom).clamp(0.0, 1.0)
+            } else {
+                0.0
+            };
+            let t = (b * s + f) / e;
+            if t < 0.0 {
+                ((-c / a).clamp(0.0, 1.0), 0.0)
+            } else if t > 1.0 {
+                (((b - c) / a).clamp(0.0, 1.0), 1.0)
+            } else {
+                (s, t)
+            }
+        }
+    };
+    let closest = (p1 + s * d1) - (p2 + t * d2);
+    (closest.length(), s, t)
+}
+
+// Length over which two parallel segments run alongside each other.
+fn parallel_overlap(p1: Vector3, q1: Vector3, p2: Vector3, q2: Vector3) -> f32 {
+    let length = (q1 - p1).length();
+    let dir = (q1 - p1).norm();
+    if length < 1e-6 || (q2 - p2).length() < 1e-6 || (dir * (q2 - p2).norm()).abs() < 1.0 - 1e-4 {
+        return 0.0;
+    }
+    let (u, v) = ((p2 - p1) * dir, (q2 - p1) * dir);
+    (u.max(v).min(length) - u.min(v).max(0.0)).max(0.0)
+}
+
+fn segments(path: &PathfindingPath) -> impl Iterator<Item = (usize, Vector3, Vector3)> + '_ {
+    path.points
+        .windows(2)
+        .enumerate()
+        .map(|(i, pair)| (i, pair[0], pair[1]))
+}
+
+// Checks every pipe against every obstacle and every other pipe, grouping the clashes by
+// the pair of items involved.
+pub fn detect_clashes(scene: &Scene, pipes: &[ClashPipe], config: &ClashConfig) -> ClashReport {
+    let mut groups: BTreeMap<(ClashItem, ClashItem), Vec<Clash>> = BTreeMap::new();
+
+    for (p, pipe) in pipes.iter().enumerate() {
+        let radius = pipe.diameter / 2.0;
+        for (element, bbox) in scene.obstacles.iter().enumerate() {
+            if !bbox.is_obstacle() {
+                continue;
+            }
+            let tolerance = config.tolerance(&pipe.class, bbox.class.as_deref().unwrap_or(""));
+            for (segment, a, b) in segments(&pipe.path) {
+                let (distance, t) = match bbox.bounds.segment_depth(a, b) {
+                    Some((inside, t)) => (-inside, t),
+                    None => bbox.bounds.segment_distance(a, b),
+                };
+                let gap = distance - radius;
+                if gap >= tolerance {
+                    continue;
+                }
+                groups
+                    .entry((ClashItem::Pipe(p), ClashItem::Element(element)))
+                    .or_default()
+                    .push(Clash {
+                        kind: if gap < 0.0 {
+                            ClashKind::Hard
+                        } else {
+                            ClashKind::Clearance
+                        },
+                        segment_a: segment,
+                        segment_b: None,
+                        position: a + t * (b - a),
+                        distance: gap,
+                    });
+            }
+        }
+    }
+
+    for (p, pipe) in pipes.iter().enumerate() {
+        for (q, other) in pipes.iter().enumerate().skip(p + 1) {
+            let tolerance = config.tolerance(&pipe.class, &other.class);
+            let radii = (pipe.diameter + other.diameter) / 2.0;
+            for (segment_a, p1, q1) in segments(&pipe.path) {
+                for (segment_b, p2, q2) in segments(&other.path) {
+                    let (distance, s, _) = segment_segment(p1, q1, p2, q2);
+                    let gap = distance - radii;
+                    if gap >= tolerance {
+                        continue;
+                    }
+                    let duplicate = distance < config.duplicate_tolerance
+                        && parallel_overlap(p1, q1, p2, q2) > config.duplicate_tolerance;
+                    let kind = if duplicate {
+                        ClashKind::Duplicate
+                    } else if gap < 0.0 {
+                        ClashKind::Hard
+                    } else {
+                        ClashKind::Clearance
+                    };
+                    groups
+                        .entry((ClashItem::Pipe(p), ClashItem::Pipe(q)))
+                        .or_default()
+                        .push(Clash {
+                            kind,
+                            segment_a,
+                            segment_b: Some(segment_b),
+                            position: p1 + s * (q1 - p1),
+                            distance: gap,
+                        });
+                }
+            }
+        }
+    }
+
+    let groups = groups
+        .into_iter()
+        .map(|((a, b), clashes)| ClashGroup {
+            a,
+            b,
+            kind: clashes.iter().map(|clash| clash.kind).min().unwrap(),
+            clashes,
+        })
+        .collect();
+    ClashReport { groups }
+}
@@ -0,0 +1,426 @@
+use std::path::Path;
+
+use crate::{
+    math::vector::Vector3,
+    scene::{BBox, Bounds, Scene},
+    utility::GenError,
+};
+
+use super::{Cell, CostModel, Grid, SearchParams};
+
+// Elements picked out by class, `*` for any, and optionally by a name pattern.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Selector {
+    pub class: Option<String>,
+    pub name: Option<String>,
+}
+
+impl Selector {
+    pub fn matches(&self, bbox: &BBox) -> bool {
+        self.class
+            .as_deref()
+            .is_none_or(|class| bbox.is_class(class))
+            && self.name.as_deref().is_none_or(|pattern| {
+                bbox.name
+                    .as_deref()
+                    .is_some_and(|name| glob(pattern.as_bytes(), name.as_bytes()))
+            })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+    Below,
+    Above,
+    // Every side.
+    From,
+}
+
+// One statement of a constraint file. A `cost` turns a hard constraint into a penalty
+// added to every step that breaks it.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Constraint {
+    Keep {
+        distance: f32,
+        side: Side,
+        selector: Selector,
+        cost: Option<f32>,
+    },
+    Avoid {
+        selector: Selector,
+        cost: Option<f32>,
+    },
+    MaxBends(usize),
+    // Cost per step for each metre the route lies outside the height range.
+    PreferHeight {
+        min: f32,
+        max: f32,
+        cost: f32,
+    },
+}
+
+// Routing constraints written one per line, for example:
+//
+//   # comments run to the end of the line, except inside quoted names
+//   keep 300 mm below IfcSlab
+//   keep 0.1 from IfcBeam cost 5
+//   avoid IfcSpace named "Server*"
+//   max 3 bends
+//   prefer z between 2.6 and 3.0 cost 2
+//
+// Lengths are in metres unless followed by mm, cm or m, and may use exponents like 1e3mm.
+#[derive(Debug, Clone, Default)]
+pub struct ConstraintSet {
+    pub constraints: Vec<Constraint>,
+}
+
+impl ConstraintSet {
+    pub fn load(path: &Path) -> GenError<Self> {
+        Self::parse(&std::fs::read_to_string(path)?)
+    }
+
+    pub fn parse(source: &str) -> GenError<Self> {
+        let mut constraints = vec![];
+        for (number, line) in source.lines().enumerate() {
+            let tokens = tokenize(line).map_err(|e| format!("line {}: {}", number + 1, e))?;
+            if tokens.is_empty() {
+                continue;
+            }
+            let constraint = Parser { tokens, at: 0 }
+                .statement()
+                .map_err(|e| format!("line {}: {}", number + 1, e))?;
+            constraints.push(constraint);
+        }
+        Ok(Self { constraints })
+    }
+
+    pub fn compile(&self, grid: &Grid, scene: &Scene) -> CompiledConstraints {
+        let mut blocked = vec![false; grid.len()];
+        let mut penalty = vec![0.0; grid.len()];
+        let mut max_bends: Option<usize> = None;
+        let mut apply = |bounds: &Bounds, cost: Option<f32>, penalty: &mut [f32]| {
+            for cell in grid.cells_in(bounds) {
+                let i = grid.index(cell);
+                match cost {
+                    Some(cost) => penalty[i] += cost,
+                    None => blocked[i] = true,
+                }
+            }
+        };
+        for constraint in self.constraints.iter() {
+            match constraint {
+                Constraint::Keep {
+                    distance,
+                    side,
+                    selector,
+                    cost,
+                } => {
+                    for bbox in scene.obstacles.iter().filter(|bbox| selector.matches(bbox)) {
+                        let (min, max) = (bbox.bounds.min, bbox.bounds.max);
+                        let region = match side {
+                            Side::Below => Bounds::new(
+                                Vector3::new(min.x, min.y, min.z - distance),
+                                Vector3::new(max.x, max.y, min.z),
+                            ),
+                            Side::Above => Bounds::new(
+                                Vector3::new(min.x, min.y, max.z),
+                                Vector3::new(max.x, max.y, max.z + distance),
+                            ),
+                            Side::From => bbox.bounds.expand(*distance),
+                        };
+                        apply(&region, *cost, &mut penalty);
+                    }
+                }
+                Constraint::Avoid { selector, cost } => {
+                    for bbox in scene.obstacles.iter().filter(|bbox| selector.matches(bbox)) {
+                        apply(&bbox.bounds, *cost, &mut penalty);
+                    }
+                }
+                Constraint::MaxBends(bends) => {
+                    max_bends = Some(max_bends.map_or(*bends, |max| max.min(*bends)));
+                }
+                Constraint::PreferHeight { min, max, cost } => {
+                    for (i, penalty) in penalty.iter_mut().enumerate() {
+                        let z = grid.cell_center(grid.cell(i)).z;
+                        *penalty += cost * (min - z).max(z - max).max(0.0);
+                    }
+                }
+            }
+        }
+        CompiledConstraints {
+            blocked,
+            penalty,
+            max_bends,
+        }
+    }
+}
+
+// Constraints resolved onto the cells of one grid.
+pub struct CompiledConstraints {
+    blocked: Vec<bool>,
+    penalty: Vec<f32>,
+    pub max_bends: Option<usize>,
+}
+
+impl CompiledConstraints {
+    pub fn is_blocked(&self, grid: &Grid, cell: Cell) -> bool {
+        self.blocked[grid.index(cell)]
+    }
+
+    pub fn penalty(&self, grid: &Grid, cell: Cell) -> f32 {
+        self.penalty[grid.index(cell)]
+    }
+
+    pub fn params(&self, params: &SearchParams) -> SearchParams {
+        SearchParams {
+            max_bends: match (params.max_bends, self.max_bends) {
+                (Some(a), Some(b)) => Some(a.min(b)),
+                (a, b) => a.or(b),
+            },
+            ..*params
+        }
+    }
+}
+
+pub struct Constrained<'a, C: CostModel> {
+    pub inner: C,
+    pub constraints: &'a CompiledConstraints,
+}
+
+impl<C: CostModel> CostModel for Constrained<'_, C> {
+    fn step_cost(&self, grid: &Grid, from: Cell, to: Cell) -> Option<f32> {
+        if self.constraints.is_blocked(grid, to) {
+            return None;
+        }
+        let cost = self.inner.step_cost(grid, from, to)?;
+        Some(cost + self.constraints.penalty(grid, to))
+    }
+
+    fn lower_bound(&self, grid: &Grid) -> f32 {
+        self.inner.lower_bound(grid)
+    }
+}
+
+// `*` matches any run of characters and `?` any single one.
+fn glob(pattern: &[u8], text: &[u8]) -> bool {
+    match (pattern.first(), text.first()) {
+        (None, None) => true,
+        (Some(b'*'), _) => {
+            glob(&pattern[1..], text) || (!text.is_empty() && glob(pattern, &text[1..]))
+        }
+        (Some(&p), Some(&t)) if p == b'?' || p == t => glob(&pattern[1..], &text[1..]),
+        _ => false,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Quoted(String),
+}
+
+fn tokenize(line: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = vec![];
+    let mut chars = line.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c == '#' {
+            // A comment, unless inside a quoted name.
+            break;
+        } else if c == '"' {
+            chars.next();
+            let mut text = String::new();
+            loop {
+                match chars.next() {
+                    Some('"') => break,
+                    Some(c) => text.push(c),
+                    None => return Err("unterminated string".to_string()),
+                }
+            }
+            tokens.push(Token::Quoted(text));
+        } else {
+            let mut word = String::new();
+            while let Some(&c) = chars.peek() {
+                if c.is_whitespace() || c == '"' || c == '#' {
+                    break;
+                }
+                word.push(c);
+                chars.next();
+            }
+            tokens.push(Token::Word(word));
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    at: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&str> {
+        match self.tokens.get(self.at) {
+            Some(Token::Word(word)) => Some(word),
+            _ => None,
+        }
+    }
+
+    fn word(&mut self) -> Result<String, String> {
+        match self.tokens.get(self.at) {
+            Some(Token::Word(word)) => {
+                self.at += 1;
+                Ok(word.clone())
+            }
+            Some(Token::Quoted(text)) => Err(format!("unexpected \"{}\"", text)),
+            None => Err("unexpected end of line".to_string()),
+        }
+    }
+
+    fn expect(&mut self, keyword: &str) -> Result<(), String> {
+        let word = self.word()?;
+        if word.eq_ignore_ascii_case(keyword) {
+            Ok(())
+        } else {
+            Err(format!("expected '{}', found '{}'", keyword, word))
+        }
+    }
+
+    fn accept(&mut self, keyword: &str) -> bool {
+        let found = self
+            .peek()
+            .is_some_and(|word| word.eq_ignore_ascii_case(keyword));
+        if found {
+            self.at += 1;
+        }
+        found
+    }
+
+    fn number(&mut self) -> Result<f32, String> {
+        let word = self.word()?;
+        word.parse()
+            .map_err(|_| format!("expected a number, found '{}'", word))
+    }
+
+    // A number, possibly in exponent form such as `1e3`, with an optional unit attached or
+    // as the next word.
+    fn length(&mut self) -> Result<f32, String> {
+        let word = self.word()?;
+        let attached = ["mm", "cm", "m"]
+            .into_iter()
+            .find_map(|unit| Some((word.strip_suffix(unit)?, unit)))
+            .filter(|(number, _)| number.parse::<f32>().is_ok());
+        let (number, unit) = match attached {
+            Some(attached) => attached,
+            None => {
+                let unit = match self.peek() {
+                    Some("mm") => "mm",
+                    Some("cm") => "cm",
+                    Some("m") => "m",
+                    _ => "",
+                };
+                if !unit.is_empty() {
+                    self.at += 1;
+                }
+                (word.as_str(), unit)
+            }
+        };
+        let value: f32 = number.parse().map_err(|_| {
+            let split = number
+                .find(|c: char| c.is_ascii_alphabetic() && c != 'e' && c != 'E')
+                .unwrap_or(number.len());
+            if split < number.len() && number[..split].parse::<f32>().is_ok() {
+                format!("unknown unit '{}'", &number[split..])
+            } else {
+                format!("expected a length, found '{}'", word)
+            }
+        })?;
+        Ok(match unit {
+            "mm" => value / 1000.0,
+            "cm" => value / 100.0,
+            _ => value,
+        })
+    }
+
+    fn selector(&mut self) -> Result<Selector, String> {
+        let class = self.word()?;
+        let name = if self.accept("named") {
+            match self.tokens.get(self.at) {
+                Some(Token::Quoted(text) | Token::Word(text)) => {
+                    self.at += 1;
+                    Some(text.clone())
+                }
+                None => return Err("expected a name after 'named'".to_string()),
+            }
+        } else {
+            None
+        };
+        Ok(Selector {
+            class: (class != "*").then_some(class),
+            name,
+        })
+    }
+
+    fn cost(&mut self) -> Result<Option<f32>, String> {
+        if self.accept("cost") {
+            self.number().map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn statement(mut self) -> Result<Constraint, String> {
+        let keyword = self.word()?.to_ascii_lowercase();
+        let constraint = match keyword.as_str() {
+            "keep" => {
+                let distance = self.length()?;
+                let side = match self.word()?.to_ascii_lowercase().as_str() {
+                    "below" => Side::Below,
+                    "above" => Side::Above,
+                    "from" => Side::From,
+                    other => {
+                        return Err(format!("expected below, above or from, found '{}'", other))
+                    }
+                };
+                Constraint::Keep {
+                    distance,
+                    side,
+                    selector: self.selector()?,
+                    cost: self.cost()?,
+                }
+            }
+            "avoid" => Constraint::Avoid {
+                selector: self.selector()?,
+                cost: self.cost()?,
+            },
+            "max" => {
+                let word = self.word()?;
+                let bends = word
+                    .parse()
+                    .map_err(|_| format!("expected a bend count, found '{}'", word))?;
+                self.expect("bends")?;
+                Constraint::MaxBends(bends)
+            }
+            "prefer" => {
+                self.expect("z")?;
+                self.expect("between")?;
+                let min = self.length()?;
+                self.expect("and")?;
+                let max = self.length()?;
+                Constraint::PreferHeight {
+                    min: min.min(max),
+                    max: min.max(max),
+                    cost: self.cost()?.unwrap_or(1.0),
+                }
+            }
+            other => return Err(format!("unknown statement '{}'", other)),
+        };
+        match self.tokens.get(self.at) {
+            None => Ok(constraint),
+            Some(Token::Word(text) | Token::Quoted(text)) => {
+                Err(format!("unexpected '{}' at end of statement", text))
+            }
+        }
+    }
+}
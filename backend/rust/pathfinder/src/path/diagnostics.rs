@@ -0,0 +1,179 @@
+use std::collections::VecDeque;
+
+use crate::{math::vector::Vector3, scene::Scene};
+
+use super::{Cell, CostModel, Grid, PenetrationPolicy, SearchParams, DIRECTIONS};
+
+pub struct Components {
+    labels: Vec<Option<usize>>,
+    pub sizes: Vec<usize>,
+}
+
+impl Components {
+    pub fn label(&self, grid: &Grid, cell: Cell) -> Option<usize> {
+        self.labels[grid.index(cell)]
+    }
+
+    pub fn len(&self) -> usize {
+        self.sizes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sizes.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockerAction {
+    Penetrate,
+    Remove,
+}
+
+#[derive(Debug, Clone)]
+pub struct Blocker {
+    // Index into `Scene::obstacles`.
+    pub element: usize,
+    pub class: Option<String>,
+    pub global_id: Option<String>,
+    pub name: Option<String>,
+    pub action: BlockerAction,
+}
+
+#[derive(Debug, Clone)]
+pub struct Diagnosis {
+    pub start_component: Option<usize>,
+    pub goal_component: Option<usize>,
+    pub start_component_size: usize,
+    pub goal_component_size: usize,
+    // Fewest elements that, once penetrated or removed, connect start and goal.
+    pub blockers: Vec<Blocker>,
+    // Route through the blockers, `None` if the search gave up before finding one.
+    pub route: Option<Vec<Cell>>,
+}
+
+impl Diagnosis {
+    pub fn is_connected(&self) -> bool {
+        self.start_component.is_some() && self.start_component == self.goal_component
+    }
+}
+
+// Entering an element costs far more than any detour through free space, so the search
+// minimises the number of elements crossed, preferring ones that may be penetrated.
+const PENETRATE_COST: f32 = 1e4;
+const REMOVE_COST: f32 = 1.5e4;
+
+struct ElementCrossing<'a> {
+    scene: &'a Scene,
+    policy: &'a PenetrationPolicy,
+}
+
+impl CostModel for ElementCrossing<'_> {
+    fn step_cost(&self, grid: &Grid, from: Cell, to: Cell) -> Option<f32> {
+        let entered = grid
+            .occupants(to)
+            .iter()
+            .filter(|element| !grid.occupants(from).contains(element))
+            .map(
+                |&element| match self.policy.cost(&self.scene.obstacles[element]) {
+                    Some(_) => PENETRATE_COST,
+                    None => REMOVE_COST,
+                },
+            )
+            .sum::<f32>();
+        Some(grid.cell_size() + entered)
+    }
+}
+
+impl Grid {
+    // Connected components of free space under the six axis-aligned moves.
+    pub fn components(&self) -> Components {
+        let mut labels = vec![None; self.len()];
+        let mut sizes = vec![];
+        let mut queue = VecDeque::new();
+        for seed in 0..self.len() {
+            if labels[seed].is_some() || !self.is_free(self.cell(seed)) {
+                continue;
+            }
+            let label = sizes.len();
+            let mut size = 0;
+            labels[seed] = Some(label);
+            queue.push_back(self.cell(seed));
+            while let Some(cell) = queue.pop_front() {
+                size += 1;
+                for dir in 0..DIRECTIONS.len() {
+                    let Some(next) = self.neighbor(cell, dir) else {
+                        continue;
+                    };
+                    let i = self.index(next);
+                    if labels[i].is_none() && self.is_free(next) {
+                        labels[i] = Some(label);
+                        queue.push_back(next);
+                    }
+                }
+            }
+            sizes.push(size);
+        }
+        Components { labels, sizes }
+    }
+
+    pub fn diagnose(
+        &self,
+        scene: &Scene,
+        start: Vector3,
+        goal: Vector3,
+        policy: &PenetrationPolicy,
+    ) -> Option<Diagnosis> {
+        let start = self.cell_at(start)?;
+        let goal = self.cell_at(goal)?;
+        let components = self.components();
+        let start_component = components.label(self, start);
+        let goal_component = components.label(self, goal);
+        let size = |label: Option<usize>| label.map_or(0, |label| components.sizes[label]);
+        let mut diagnosis = Diagnosis {
+            start_component,
+            goal_component,
+            start_component_size: size(start_component),
+            goal_component_size: size(goal_component),
+            blockers: vec![],
+            route: None,
+        };
+        if diagnosis.is_connected() {
+            return Some(diagnosis);
+        }
+
+        let cost = ElementCrossing { scene, policy };
+        let params = SearchParams {
+            bend_cost: 0.0,
+            ..SearchParams::default()
+        };
+        let Some(cells) = self.search(&[start], &[goal], &cost, &params) else {
+            return Some(diagnosis);
+        };
+        let mut elements: Vec<usize> = vec![];
+        for &cell in cells.iter() {
+            for &element in self.occupants(cell) {
+                if !elements.contains(&element) {
+                    elements.push(element);
+                }
+            }
+        }
+        diagnosis.blockers = elements
+            .into_iter()
+            .map(|element| {
+                let bbox = &scene.obstacles[element];
+                Blocker {
+                    element,
+                    class: bbox.class.clone(),
+                    global_id: bbox.global_id.clone(),
+                    name: bbox.name.clone(),
+                    action: match policy.cost(bbox) {
+                        Some(_) => BlockerAction::Penetrate,
+                        None => BlockerAction::Remove,
+                    },
+                }
+            })
+            .collect();
+        diagnosis.route = Some(cells);
+        Some(diagnosis)
+    }
+}
@@ -0,0 +1,355 @@
+use std::{
+    cell::RefCell,
+    cmp::Ordering,
+    collections::{BinaryHeap, HashMap},
+};
+
+use crate::math::vector::Vector3;
+
+use super::{direction_vector, opposite, Cell, Grid, PathfindingPath, DIRECTIONS};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Profile {
+    pub width: f32,
+    pub height: f32,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct DuctConfig {
+    // Kept free around the profile on every side.
+    pub clearance: f32,
+    pub bend_cost: f32,
+    // Cost of twisting the profile about its own axis.
+    pub roll_cost: f32,
+    pub max_expansions: usize,
+}
+
+impl Default for DuctConfig {
+    fn default() -> Self {
+        Self {
+            clearance: 0.05,
+            bend_cost: 1.0,
+            roll_cost: 4.0,
+            max_expansions: 2_000_000,
+        }
+    }
+}
+
+// Leg of a duct run: the route up to `to` is made with `profile`.
+#[derive(Debug, Clone, Copy)]
+pub struct DuctSection {
+    pub to: Vector3,
+    pub profile: Profile,
+}
+
+#[derive(Debug, Clone)]
+pub struct DuctSegment {
+    pub from: Vector3,
+    pub to: Vector3,
+    pub profile: Profile,
+    // Direction the height of the profile is measured along.
+    pub up: Vector3,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DuctFittingKind {
+    // A bend in the plane of the width (a flat bend) or of the height.
+    Elbow { flat: bool },
+    Twist,
+    Transition { from: Profile, to: Profile },
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct DuctFitting {
+    pub kind: DuctFittingKind,
+    pub position: Vector3,
+}
+
+#[derive(Debug, Clone)]
+pub struct DuctRoute {
+    pub path: PathfindingPath,
+    pub segments: Vec<DuctSegment>,
+    pub fittings: Vec<DuctFitting>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct State {
+    cell: Cell,
+    dir: Option<usize>,
+    // Axis the profile height lies along, always across `dir`.
+    up: usize,
+}
+
+struct Open {
+    f: f32,
+    state: State,
+}
+
+impl PartialEq for Open {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Open {}
+
+impl PartialOrd for Open {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Open {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.f.total_cmp(&self.f)
+    }
+}
+
+fn axis_of(cell: Cell, axis: usize) -> usize {
+    [cell.x, cell.y, cell.z][axis]
+}
+
+// Orientation after turning from `from` into `to` without twisting: the profile axis that
+// lay along the new direction swings round onto the old one.
+fn carried_up(up: usize, from: usize, to: usize) -> usize {
+    if up == to / 2 {
+        from / 2
+    } else {
+        up
+    }
+}
+
+struct Sweep<'a> {
+    grid: &'a Grid,
+    // Half extents of the profile, with clearance, in whole cells.
+    half_width: usize,
+    half_height: usize,
+    cache: RefCell<HashMap<(Cell, usize, usize), bool>>,
+}
+
+impl Sweep<'_> {
+    // The cross-section centred on `cell`, facing along `axis` with its height along `up`,
+    // lies in free cells only.
+    fn fits(&self, cell: Cell, axis: usize, up: usize) -> bool {
+        let key = (cell, axis, up);
+        if let Some(&fits) = self.cache.borrow().get(&key) {
+            return fits;
+        }
+        let side = 3 - axis - up;
+        let extent = self.grid.extent();
+        let range = |a: usize, half: usize| {
+            let v = axis_of(cell, a);
+            let limit = axis_of(extent, a);
+            (v >= half && v + half < limit).then(|| v - half..=v + half)
+        };
+        let fits = match (range(up, self.half_height), range(side, self.half_width)) {
+            (Some(heights), Some(widths)) => heights.into_iter().all(|h| {
+                widths.clone().all(|w| {
+                    let mut c = [cell.x, cell.y, cell.z];
+                    c[up] = h;
+                    c[side] = w;
+                    self.grid.is_free(Cell::new(c[0], c[1], c[2]))
+                })
+            }),
+            _ => false,
+        };
+        self.cache.borrow_mut().insert(key, fits);
+        fits
+    }
+}
+
+impl Grid {
+    fn duct_search(
+        &self,
+        start: Cell,
+        goal: Cell,
+        // Direction and orientation the duct arrives with, when continuing a previous leg.
+        entry: Option<(usize, usize)>,
+        profile: Profile,
+        config: &DuctConfig,
+    ) -> Option<Vec<State>> {
+        let half = |size: f32| {
+            ((size / 2.0 + config.clearance) / self.cell_size() - 0.5)
+                .ceil()
+                .max(0.0) as usize
+        };
+        let sweep = Sweep {
+            grid: self,
+            half_width: half(profile.width),
+            half_height: half(profile.height),
+            cache: RefCell::new(HashMap::new()),
+        };
+        let heuristic = |cell: Cell| cell.manhattan(&goal) as f32 * self.cell_size();
+        let mut open = BinaryHeap::new();
+        let mut g_score: HashMap<State, f32> = HashMap::new();
+        let mut came_from: HashMap<State, State> = HashMap::new();
+        let seeds = match entry {
+            Some((dir, up)) => vec![(Some(dir), up)],
+            None => (0..3).map(|up| (None, up)).collect(),
+        };
+        for (dir, up) in seeds {
+            let state = State {
+                cell: start,
+                dir,
+                up,
+            };
+            g_score.insert(state, 0.0);
+            open.push(Open {
+                f: heuristic(start),
+                state,
+            });
+        }
+
+        let mut expansions = 0;
+        while let Some(Open { f, state }) = open.pop() {
+            let g = g_score[&state];
+            if f > g + heuristic(state.cell) + 1e-4 {
+                continue;
+            }
+            if state.cell == goal && state.dir.is_some() {
+                let mut states = vec![state];
+                let mut current = state;
+                while let Some(&previous) = came_from.get(&current) {
+                    states.push(previous);
+                    current = previous;
+                }
+                states.reverse();
+                return Some(states);
+            }
+            expansions += 1;
+            if expansions > config.max_expansions {
+                return None;
+            }
+            for dir in 0..DIRECTIONS.len() {
+                // A duct cannot fold back onto the cell it just left.
+                if state.dir.is_some_and(|prev| dir == opposite(prev)) {
+                    continue;
+                }
+                let Some(next) = self.neighbor(state.cell, dir) else {
+                    continue;
+                };
+                let turns = state.dir.is_some_and(|prev| prev != dir);
+                let natural = match state.dir {
+                    Some(prev) => carried_up(state.up, prev, dir),
+                    None => state.up,
+                };
+                for up in (0..3).filter(|&up| up != dir / 2) {
+                    let rolled = up != natural;
+                    // The cross-section must also fit where the duct starts, turns or rolls.
+                    let reshaped = turns || rolled || state.dir.is_none();
+                    if (state.dir.is_none() && rolled)
+                        || (reshaped && !sweep.fits(state.cell, dir / 2, up))
+                        || !sweep.fits(next, dir / 2, up)
+                    {
+                        continue;
+                    }
+                    let mut cost = self.cell_size();
+                    if turns {
+                        cost += config.bend_cost;
+                    }
+                    if rolled {
+                        cost += config.roll_cost;
+                    }
+                    let next_state = State {
+                        cell: next,
+                        dir: Some(dir),
+                        up,
+                    };
+                    let tentative = g + cost;
+                    if tentative < *g_score.get(&next_state).unwrap_or(&f32::INFINITY) {
+                        g_score.insert(next_state, tentative);
+                        came_from.insert(next_state, state);
+                        open.push(Open {
+                            f: tentative + heuristic(next),
+                            state: next_state,
+                        });
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    // Routes a rectangular duct or tray from `start` through each section in turn, carrying
+    // the orientation across section boundaries and adding a transition wherever the size
+    // changes.
+    pub fn route_duct(
+        &self,
+        start: Vector3,
+        sections: &[DuctSection],
+        config: &DuctConfig,
+    ) -> Option<DuctRoute> {
+        let mut route = DuctRoute {
+            path: PathfindingPath { points: vec![] },
+            segments: vec![],
+            fittings: vec![],
+        };
+        let mut from = self.cell_at(start)?;
+        let mut entry = None;
+        let mut previous: Option<(Profile, State)> = None;
+        for section in sections {
+            let to = self.cell_at(section.to)?;
+            let states = self.duct_search(from, to, entry, section.profile, config)?;
+            if let Some((profile, last)) = previous {
+                if profile != section.profile {
+                    route.fittings.push(DuctFitting {
+                        kind: DuctFittingKind::Transition {
+                            from: profile,
+                            to: section.profile,
+                        },
+                        position: self.cell_center(last.cell),
+                    });
+                }
+            }
+
+            // Split the states into straight runs of constant orientation.
+            let fresh = usize::from(states[0].dir.is_none());
+            for pair in states.windows(2).skip(fresh) {
+                let (a, b) = (pair[0], pair[1]);
+                let position = self.cell_center(a.cell);
+                if a.dir != b.dir {
+                    let plane = (a.dir.unwrap() / 2, b.dir.unwrap() / 2);
+                    route.fittings.push(DuctFitting {
+                        kind: DuctFittingKind::Elbow {
+                            flat: plane.0 != a.up && plane.1 != a.up,
+                        },
+                        position,
+                    });
+                }
+                if b.up != carried_up(a.up, a.dir.unwrap(), b.dir.unwrap()) {
+                    route.fittings.push(DuctFitting {
+                        kind: DuctFittingKind::Twist,
+                        position,
+                    });
+                }
+            }
+            let mut run_start = states[0];
+            for (i, state) in states.iter().enumerate().skip(1) {
+                let ends_run = states
+                    .get(i + 1)
+                    .is_none_or(|next| next.dir != state.dir || next.up != state.up);
+                if ends_run {
+                    route.segments.push(DuctSegment {
+                        from: self.cell_center(run_start.cell),
+                        to: self.cell_center(state.cell),
+                        profile: section.profile,
+                        up: direction_vector(2 * state.up),
+                    });
+                    run_start = *state;
+                }
+            }
+
+            let last = *states.last().unwrap();
+            let cells: Vec<Cell> = states.iter().map(|state| state.cell).collect();
+            let mut points = self.cells_to_path(&cells).merge_collinear(1e-4).points;
+            if !route.path.points.is_empty() {
+                points.remove(0);
+            }
+            route.path.points.extend(points);
+            previous = Some((section.profile, last));
+            entry = last.dir.map(|dir| (dir, last.up));
+            from = to;
+        }
+        Some(route)
+    }
+}
@@ -0,0 +1,227 @@
+use crate::math::vector::Vector3;
+
+use super::{direction_vector, nearest_direction, Grid, PathfindingPath, DIRECTIONS};
+
+#[derive(Debug, Clone, Copy)]
+pub struct ExpansionConfig {
+    // Linear expansion coefficient in 1/K.
+    pub coefficient: f32,
+    pub temperature_delta: f32,
+    // Axial movement a straight run may take up on its own, through anchors and guides.
+    pub max_movement: f32,
+    // Young's modulus and allowable bending stress, in Pa, for sizing the loop legs.
+    pub modulus: f32,
+    pub allowable_stress: f32,
+    pub diameter: f32,
+    // Width of a U-loop as a fraction of its leg length.
+    pub width_ratio: f32,
+}
+
+impl Default for ExpansionConfig {
+    // Carbon steel carrying water 60 K above installation temperature.
+    fn default() -> Self {
+        Self {
+            coefficient: 1.2e-5,
+            temperature_delta: 60.0,
+            max_movement: 0.02,
+            modulus: 2.0e11,
+            allowable_stress: 1.0e8,
+            diameter: 0.06,
+            width_ratio: 0.5,
+        }
+    }
+}
+
+impl ExpansionConfig {
+    // Longest straight run that needs no loop.
+    pub fn allowable_length(&self) -> f32 {
+        self.max_movement / (self.coefficient * self.temperature_delta)
+    }
+
+    // Guided cantilever: leg length that takes up `movement` within the allowable stress.
+    pub fn leg_length(&self, movement: f32) -> f32 {
+        (3.0 * self.modulus * self.diameter * movement / self.allowable_stress).sqrt()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoopKind {
+    U,
+    Z,
+}
+
+#[derive(Debug, Clone)]
+pub struct ExpansionLoop {
+    pub kind: LoopKind,
+    // Segment of the original route the loop was inserted into.
+    pub segment: usize,
+    pub position: Vector3,
+    pub leg: f32,
+    pub width: f32,
+    pub movement: f32,
+}
+
+#[derive(Debug, Clone)]
+pub struct LongRun {
+    pub segment: usize,
+    pub length: f32,
+    pub movement: f32,
+    // Loops the run needs and how many could not be placed.
+    pub required: usize,
+    pub missing: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct ExpansionResult {
+    pub path: PathfindingPath,
+    pub loops: Vec<ExpansionLoop>,
+    pub unresolved: Vec<LongRun>,
+}
+
+struct Fit<'a> {
+    grid: &'a Grid,
+    clearance: Vec<u32>,
+    radius: u32,
+}
+
+impl Fit<'_> {
+    // Every cell along the polyline is free with room for the pipe around it.
+    fn clear(&self, points: &[Vector3]) -> bool {
+        let step = self.grid.cell_size() / 2.0;
+        points.windows(2).all(|pair| {
+            let length = (pair[1] - pair[0]).length();
+            let samples = (length / step).ceil().max(1.0) as usize;
+            (0..=samples).all(|k| {
+                let point = pair[0] + (k as f32 / samples as f32) * (pair[1] - pair[0]);
+                self.grid.cell_at(point).is_some_and(|cell| {
+                    self.grid.is_free(cell) && self.clearance[self.grid.index(cell)] >= self.radius
+                })
+            })
+        })
+    }
+}
+
+fn perpendiculars(dir: Vector3) -> Vec<Vector3> {
+    let axis = nearest_direction(dir) / 2;
+    (0..DIRECTIONS.len())
+        .filter(|&d| d / 2 != axis)
+        .map(direction_vector)
+        .collect()
+}
+
+// Inserts loops into every straight run longer than the allowable length, spacing them
+// evenly and sliding each one along the run until it fits in free space.
+pub fn insert_expansion_loops(
+    grid: &Grid,
+    path: &PathfindingPath,
+    config: &ExpansionConfig,
+) -> ExpansionResult {
+    let path = path.merge_collinear(1e-4);
+    let fit = Fit {
+        grid,
+        clearance: grid.clearance(),
+        radius: (config.diameter / 2.0 / grid.cell_size() - 0.5)
+            .ceil()
+            .max(0.0) as u32,
+    };
+    let allowable = config.allowable_length();
+    let mut result = ExpansionResult {
+        path: PathfindingPath { points: vec![] },
+        loops: vec![],
+        unresolved: vec![],
+    };
+    let points = &path.points;
+    // Segments start from the last point written, which a Z-offset may have moved.
+    let mut out: Vec<Vector3> = points.iter().take(1).copied().collect();
+
+    for (segment, pair) in points.windows(2).enumerate() {
+        let (a, b) = (*out.last().unwrap(), pair[1]);
+        let length = (b - a).length();
+        if length <= allowable || length < 1e-6 {
+            out.push(b);
+            continue;
+        }
+        let dir = (b - a).norm();
+        let required = (length / allowable).ceil() as usize - 1;
+        let part = length / (required + 1) as f32;
+        let movement = config.coefficient * config.temperature_delta * part;
+
+        // Each U-loop takes up the movement of the run on either side, half on each leg.
+        let leg = config.leg_length(movement / 2.0).max(grid.cell_size());
+        let width = (leg * config.width_ratio).max(grid.cell_size());
+        let slide = (part / 4.0 / grid.cell_size()).floor() as i32;
+        let mut placed = 0;
+        for k in 1..=required {
+            let ideal = k as f32 * part;
+            let loop_points = (0..=slide)
+                .flat_map(|j| [j, -j])
+                .map(|j| ideal + j as f32 * grid.cell_size())
+                .filter(|&at| at - width / 2.0 > 0.0 && at + width / 2.0 < length)
+                .flat_map(|at| perpendiculars(dir).into_iter().map(move |n| (at, n)))
+                .map(|(at, n)| {
+                    let start = a + (at - width / 2.0) * dir;
+                    let end = a + (at + width / 2.0) * dir;
+                    (at, vec![start, start + leg * n, end + leg * n, end])
+                })
+                .find(|(_, polyline)| fit.clear(polyline));
+            if let Some((at, polyline)) = loop_points {
+                out.extend(polyline);
+                placed += 1;
+                result.loops.push(ExpansionLoop {
+                    kind: LoopKind::U,
+                    segment,
+                    position: a + at * dir,
+                    leg,
+                    width,
+                    movement,
+                });
+            }
+        }
+
+        // A single missing loop can become a Z-offset when the next segment turns off the
+        // run: the tail of the run moves sideways and shortens or lengthens that segment.
+        let missing = required - placed;
+        let total_movement = config.coefficient * config.temperature_delta * length;
+        if required == 1 && missing == 1 {
+            let z_leg = config.leg_length(total_movement);
+            let at = a + (length / 2.0) * dir;
+            let offset = points
+                .get(segment + 2)
+                .filter(|&&c| {
+                    (dir * (c - b).norm()).abs() < 1e-3
+                        && (c - b).length() > z_leg + grid.cell_size()
+                })
+                .and_then(|&c| {
+                    let next = (c - b).norm();
+                    [next, -next]
+                        .into_iter()
+                        .map(|n| vec![at, at + z_leg * n, b + z_leg * n, c])
+                        .find(|polyline| fit.clear(polyline))
+                });
+            if let Some(polyline) = offset {
+                out.extend(polyline.into_iter().take(3));
+                result.loops.push(ExpansionLoop {
+                    kind: LoopKind::Z,
+                    segment,
+                    position: at,
+                    leg: z_leg,
+                    width: 0.0,
+                    movement: total_movement,
+                });
+                continue;
+            }
+        }
+        if missing > 0 {
+            result.unresolved.push(LongRun {
+                segment,
+                length,
+                movement: total_movement,
+                required,
+                missing,
+            });
+        }
+        out.push(b);
+    }
+    result.path = PathfindingPath { points: out };
+    result
+}
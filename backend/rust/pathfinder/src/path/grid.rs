@@ -1,15 +1,73 @@
+use std::collections::{HashMap, VecDeque};
+
 use crate::{
     math::{matrix::Matrix4, vector::Vector3},
-    scene::{Bounds, Scene},
+    scene::{Bounds, Scene, SceneChange},
+    utility::GenError,
 };
 
-use super::VoxelStrategy;
+use super::{PathfindingPath, VoxelStrategy};
+
+pub const DIRECTIONS: [[i32; 3]; 6] = [
+    [1, 0, 0],
+    [-1, 0, 0],
+    [0, 1, 0],
+    [0, -1, 0],
+    [0, 0, 1],
+    [0, 0, -1],
+];
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Cell {
+    pub x: usize,
+    pub y: usize,
+    pub z: usize,
+}
+
+impl Cell {
+    pub fn new(x: usize, y: usize, z: usize) -> Self {
+        Self { x, y, z }
+    }
+
+    pub fn manhattan(&self, other: &Cell) -> usize {
+        self.x.abs_diff(other.x) + self.y.abs_diff(other.y) + self.z.abs_diff(other.z)
+    }
+
+    pub fn chebyshev(&self, other: &Cell) -> usize {
+        self.x
+            .abs_diff(other.x)
+            .max(self.y.abs_diff(other.y))
+            .max(self.z.abs_diff(other.z))
+    }
+}
+
+pub fn direction_vector(dir: usize) -> Vector3 {
+    let [x, y, z] = DIRECTIONS[dir];
+    Vector3::new(x as f32, y as f32, z as f32)
+}
+
+pub fn opposite(dir: usize) -> usize {
+    dir ^ 1
+}
+
+pub fn direction_between(from: Cell, to: Cell) -> Option<usize> {
+    let delta = [
+        to.x as i64 - from.x as i64,
+        to.y as i64 - from.y as i64,
+        to.z as i64 - from.z as i64,
+    ];
+    DIRECTIONS
+        .iter()
+        .position(|dir| dir.iter().zip(delta).all(|(&d, delta)| d as i64 == delta))
+}
 
 pub struct Grid {
     num_rows: usize,
     num_cols: usize,
-    _num_layers: usize,
+    num_layers: usize,
     cells: Vec<bool>,
+    // Indices into `Scene::obstacles` of every element overlapping a blocked cell.
+    occupants: HashMap<usize, Vec<usize>>,
     cell_size: f32,
     bounds: Bounds,
 }
@@ -21,27 +79,117 @@ impl Grid {
         let num_cols = ((bounds.dimensions().y + cell_size) / cell_size) as usize;
         let num_layers = ((bounds.dimensions().z + cell_size) / cell_size) as usize;
         let mut cells = vec![true; num_rows * num_cols * num_layers];
-        for i in 0..(num_rows * num_cols * num_layers) {
-            if cells[i] {
-                let cell_bounds = cell_bounds_from_index(i, num_cols, num_rows, cell_size, bounds);
-                for obstacle in scene.get_obstacles().iter() {
-                    if S::is_valid(&obstacle.bounds, &cell_bounds) {
-                        cells[i] = false;
-                        break;
-                    }
+        let mut occupants: HashMap<usize, Vec<usize>> = HashMap::new();
+        for (i, free) in cells.iter_mut().enumerate() {
+            let cell_bounds = cell_bounds_from_index(i, num_cols, num_rows, cell_size, bounds);
+            for (index, obstacle) in scene.obstacles.iter().enumerate() {
+                if obstacle.is_obstacle() && S::is_valid(&obstacle.bounds, &cell_bounds) {
+                    *free = false;
+                    occupants.entry(i).or_default().push(index);
                 }
             }
         }
         Self {
             num_rows,
             num_cols,
-            _num_layers: num_layers,
+            num_layers,
             cells,
+            occupants,
             cell_size,
             bounds,
         }
     }
 
+    // Blocks every cell the obstacle at `element` overlaps under strategy `S`. Returns the
+    // cells that were free before.
+    pub fn insert_obstacle<S: VoxelStrategy>(
+        &mut self,
+        element: usize,
+        bounds: &Bounds,
+    ) -> Vec<Cell> {
+        let mut changed = vec![];
+        for i in self.candidates::<S>(bounds) {
+            if !S::is_valid(bounds, &self.cell_bounds(i)) {
+                continue;
+            }
+            let occupants = self.occupants.entry(i).or_default();
+            if !occupants.contains(&element) {
+                occupants.push(element);
+            }
+            if self.cells[i] {
+                self.cells[i] = false;
+                changed.push(self.cell(i));
+            }
+        }
+        changed
+    }
+
+    // Takes `element` out of every cell it occupies. Returns the cells left free.
+    pub fn remove_obstacle(&mut self, element: usize) -> Vec<Cell> {
+        let mut changed = vec![];
+        self.occupants.retain(|&i, occupants| {
+            occupants.retain(|&e| e != element);
+            if occupants.is_empty() {
+                self.cells[i] = true;
+                changed.push(i);
+            }
+            !occupants.is_empty()
+        });
+        changed.sort_unstable();
+        changed.into_iter().map(|i| self.cell(i)).collect()
+    }
+
+    // Follows an edit of the scene the grid was built from, keeping occupant indices in step
+    // with `Scene::obstacles`. Returns every cell whose occupants changed, or an error, with
+    // the grid untouched, when the element now reaches outside it.
+    pub fn apply_change<S: VoxelStrategy>(&mut self, change: &SceneChange) -> GenError<Vec<Cell>> {
+        if change.leaves(&self.bounds) {
+            return Err("changed element reaches outside the grid, which must be rebuilt".into());
+        }
+        let element = change.element;
+        let mut changed = vec![];
+        match (&change.before, &change.after) {
+            (Some(_), None) => {
+                changed = self.remove_obstacle(element);
+                self.renumber(|e| if e > element { e - 1 } else { e });
+            }
+            (None, Some(_)) => self.renumber(|e| if e >= element { e + 1 } else { e }),
+            _ => changed = self.remove_obstacle(element),
+        }
+        if let Some(bbox) = change.after.as_ref().filter(|bbox| bbox.is_obstacle()) {
+            changed.extend(self.insert_obstacle::<S>(element, &bbox.bounds));
+        }
+        if let Some(bbox) = change.before.as_ref().filter(|bbox| bbox.is_obstacle()) {
+            // Cells the element still blocks may have other costs, e.g. a new class.
+            for i in self.candidates::<S>(&bbox.bounds) {
+                if !self.cells[i] {
+                    changed.push(self.cell(i));
+                }
+            }
+        }
+        changed.sort_unstable();
+        changed.dedup();
+        Ok(changed)
+    }
+
+    // Indices of the cells an obstacle with `bounds` may block under strategy `S`: those under
+    // the bounds, or every cell for strategies such as `Neg` that block outside them.
+    fn candidates<S: VoxelStrategy>(&self, bounds: &Bounds) -> Vec<usize> {
+        if S::LOCAL {
+            self.cells_in(bounds).map(|cell| self.index(cell)).collect()
+        } else {
+            (0..self.cells.len()).collect()
+        }
+    }
+
+    fn renumber(&mut self, f: impl Fn(usize) -> usize) {
+        for occupants in self.occupants.values_mut() {
+            for element in occupants.iter_mut() {
+                *element = f(*element);
+            }
+        }
+    }
+
     pub fn get_bounds_instances(&self) -> Vec<Matrix4> {
         let mut instances: Vec<_> = self
             .cells
@@ -59,6 +207,153 @@ impl Grid {
         instances
     }
 
+    pub fn cell_size(&self) -> f32 {
+        self.cell_size
+    }
+
+    pub fn bounds(&self) -> Bounds {
+        self.bounds
+    }
+
+    pub fn len(&self) -> usize {
+        self.cells.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cells.is_empty()
+    }
+
+    pub fn extent(&self) -> Cell {
+        Cell::new(self.num_rows, self.num_cols, self.num_layers)
+    }
+
+    pub fn index(&self, cell: Cell) -> usize {
+        cell.z * self.num_cols * self.num_rows + cell.x * self.num_cols + cell.y
+    }
+
+    pub fn cell(&self, i: usize) -> Cell {
+        let (layer, row, col) = cell_xyz(i, self.num_cols, self.num_rows);
+        Cell::new(col, row, layer)
+    }
+
+    pub fn contains_cell(&self, cell: Cell) -> bool {
+        cell.x < self.num_rows && cell.y < self.num_cols && cell.z < self.num_layers
+    }
+
+    pub fn is_free(&self, cell: Cell) -> bool {
+        self.contains_cell(cell) && self.cells[self.index(cell)]
+    }
+
+    pub fn occupants(&self, cell: Cell) -> &[usize] {
+        self.occupants
+            .get(&self.index(cell))
+            .map_or(&[], |occupants| occupants.as_slice())
+    }
+
+    pub fn cell_at(&self, point: Vector3) -> Option<Cell> {
+        let local = (point - self.bounds.min) / self.cell_size;
+        if local.x < 0.0 || local.y < 0.0 || local.z < 0.0 {
+            return None;
+        }
+        let cell = Cell::new(local.x as usize, local.y as usize, local.z as usize);
+        self.contains_cell(cell).then_some(cell)
+    }
+
+    pub fn cell_center(&self, cell: Cell) -> Vector3 {
+        get_cell_bounds(cell.y, cell.x, cell.z, self.cell_size, self.bounds).midpoint()
+    }
+
+    pub fn neighbor(&self, cell: Cell, dir: usize) -> Option<Cell> {
+        let [dx, dy, dz] = DIRECTIONS[dir];
+        let neighbor = Cell::new(
+            cell.x.checked_add_signed(dx as isize)?,
+            cell.y.checked_add_signed(dy as isize)?,
+            cell.z.checked_add_signed(dz as isize)?,
+        );
+        self.contains_cell(neighbor).then_some(neighbor)
+    }
+
+    pub fn cells_within(&self, cell: Cell, radius: usize) -> impl Iterator<Item = Cell> + '_ {
+        let extent = self.extent();
+        let (x0, x1) = (
+            cell.x.saturating_sub(radius),
+            (cell.x + radius).min(extent.x - 1),
+        );
+        let (y0, y1) = (
+            cell.y.saturating_sub(radius),
+            (cell.y + radius).min(extent.y - 1),
+        );
+        let (z0, z1) = (
+            cell.z.saturating_sub(radius),
+            (cell.z + radius).min(extent.z - 1),
+        );
+        (z0..=z1).flat_map(move |z| {
+            (x0..=x1).flat_map(move |x| (y0..=y1).map(move |y| Cell::new(x, y, z)))
+        })
+    }
+
+    // Cells overlapping `bounds`, clamped to the grid.
+    pub fn cells_in(&self, bounds: &Bounds) -> impl Iterator<Item = Cell> + '_ {
+        let extent = self.extent();
+        let range = |min: f32, max: f32, origin: f32, count: usize| {
+            let end = (((max - origin) / self.cell_size).ceil().max(0.0) as usize).min(count);
+            let start = (((min - origin) / self.cell_size).floor().max(0.0) as usize).min(end);
+            start..end
+        };
+        let xs = range(bounds.min.x, bounds.max.x, self.bounds.min.x, extent.x);
+        let ys = range(bounds.min.y, bounds.max.y, self.bounds.min.y, extent.y);
+        let zs = range(bounds.min.z, bounds.max.z, self.bounds.min.z, extent.z);
+        zs.flat_map(move |z| {
+            let ys = ys.clone();
+            xs.clone()
+                .flat_map(move |x| ys.clone().map(move |y| Cell::new(x, y, z)))
+        })
+    }
+
+    // Chebyshev distance, in whole cells, from each free cell to the nearest blocked cell or
+    // the grid boundary; a pipe of radius `k` cells fits wherever the value is at least `k`.
+    pub fn clearance(&self) -> Vec<u32> {
+        let mut clearance = vec![u32::MAX; self.cells.len()];
+        let mut queue = VecDeque::new();
+        let extent = self.extent();
+        for (i, &free) in self.cells.iter().enumerate() {
+            let cell = self.cell(i);
+            let on_boundary = cell.x == 0
+                || cell.y == 0
+                || cell.z == 0
+                || cell.x + 1 == extent.x
+                || cell.y + 1 == extent.y
+                || cell.z + 1 == extent.z;
+            if !free {
+                clearance[i] = 0;
+                queue.push_back(i);
+            } else if on_boundary {
+                clearance[i] = 1;
+                queue.push_back(i);
+            }
+        }
+        while let Some(i) = queue.pop_front() {
+            let cell = self.cell(i);
+            for neighbor in self.cells_within(cell, 1) {
+                let j = self.index(neighbor);
+                if clearance[j] > clearance[i] + 1 {
+                    clearance[j] = clearance[i] + 1;
+                    queue.push_back(j);
+                }
+            }
+        }
+        clearance
+            .into_iter()
+            .map(|distance| distance.saturating_sub(1))
+            .collect()
+    }
+
+    pub fn cells_to_path(&self, cells: &[Cell]) -> PathfindingPath {
+        PathfindingPath {
+            points: cells.iter().map(|&cell| self.cell_center(cell)).collect(),
+        }
+    }
+
     fn cell_bounds(&self, i: usize) -> Bounds {
         cell_bounds_from_index(i, self.num_cols, self.num_rows, self.cell_size, self.bounds)
     }
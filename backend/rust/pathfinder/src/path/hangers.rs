@@ -0,0 +1,211 @@
+use crate::{math::vector::Vector3, scene::Scene};
+
+use super::{PathfindingPath, PipeSpec};
+
+#[derive(Debug, Clone)]
+pub struct SpacingRule {
+    pub material: String,
+    // The rule applies to pipes up to this outside diameter.
+    pub max_diameter: f32,
+    pub spacing: f32,
+}
+
+#[derive(Debug, Clone)]
+pub struct HangerConfig {
+    pub rules: Vec<SpacingRule>,
+    // Used when no rule matches the pipe.
+    pub default_spacing: f32,
+    pub max_rod_length: f32,
+    pub structure_classes: Vec<String>,
+}
+
+impl Default for HangerConfig {
+    fn default() -> Self {
+        let rules = [
+            ("Steel", 0.034, 2.1),
+            ("Steel", 0.061, 3.0),
+            ("Steel", 0.089, 3.7),
+            ("Steel", 0.169, 4.3),
+            ("Steel", f32::INFINITY, 5.8),
+            ("Copper", 0.029, 1.8),
+            ("Copper", 0.055, 2.4),
+            ("Copper", 0.080, 3.0),
+            ("Copper", f32::INFINITY, 3.7),
+        ]
+        .into_iter()
+        .map(|(material, max_diameter, spacing)| SpacingRule {
+            material: material.to_string(),
+            max_diameter,
+            spacing,
+        })
+        .collect();
+        Self {
+            rules,
+            default_spacing: 2.0,
+            max_rod_length: 1.5,
+            structure_classes: vec!["IfcSlab".to_string(), "IfcBeam".to_string()],
+        }
+    }
+}
+
+impl HangerConfig {
+    pub fn spacing(&self, spec: &PipeSpec) -> f32 {
+        self.rules
+            .iter()
+            .filter(|rule| rule.material.eq_ignore_ascii_case(&spec.material))
+            .filter(|rule| spec.diameter <= rule.max_diameter)
+            .min_by(|a, b| a.max_diameter.total_cmp(&b.max_diameter))
+            .map_or(self.default_spacing, |rule| rule.spacing)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Hanger {
+    pub position: Vector3,
+    pub segment: usize,
+    // Distance along the route.
+    pub offset: f32,
+    // Index into `Scene::obstacles` of the nearest structure above, if any.
+    pub structure: Option<usize>,
+    // From the top of the pipe to the underside of `structure`.
+    pub rod_length: Option<f32>,
+    // Elements the rod would pass through on its way up.
+    pub obstructed_by: Vec<usize>,
+}
+
+impl Hanger {
+    pub fn is_reachable(&self, max_rod_length: f32) -> bool {
+        self.rod_length
+            .is_some_and(|length| length <= max_rod_length)
+    }
+}
+
+// Stretch of the route between two anchored hangers with at least one hanger in between
+// that cannot reach structure.
+#[derive(Debug, Clone)]
+pub struct UnsupportedSpan {
+    pub start: f32,
+    pub end: f32,
+    pub hangers: Vec<usize>,
+}
+
+#[derive(Debug, Clone)]
+pub struct HangerPlan {
+    pub spacing: f32,
+    pub hangers: Vec<Hanger>,
+    pub unsupported: Vec<UnsupportedSpan>,
+}
+
+// Hangers go at every bend and along horizontal and sloped runs at no more than the spacing
+// for the pipe. Risers are only held at their ends.
+pub fn place_hangers(
+    scene: &Scene,
+    path: &PathfindingPath,
+    spec: &PipeSpec,
+    config: &HangerConfig,
+) -> HangerPlan {
+    let path = path.merge_collinear(1e-4);
+    let spacing = config.spacing(spec);
+    let offsets = path.offsets();
+    let mut stations: Vec<(usize, f32)> = vec![];
+    for (segment, pair) in path.points.windows(2).enumerate() {
+        let d = pair[1] - pair[0];
+        let length = d.length();
+        if segment > 0 {
+            stations.push((segment, offsets[segment]));
+        }
+        if length < 1e-6 || (d.z / length).abs() > 0.99 {
+            continue;
+        }
+        let spans = (length / spacing).ceil() as usize;
+        for k in 1..spans {
+            stations.push((segment, offsets[segment] + k as f32 * length / spans as f32));
+        }
+    }
+
+    let radius = spec.diameter / 2.0;
+    let hangers: Vec<Hanger> = stations
+        .into_iter()
+        .map(|(segment, offset)| {
+            let (_, position) = path.point_at(offset);
+            let top = position.z + radius;
+            let structure = scene
+                .obstacles
+                .iter()
+                .enumerate()
+                .filter(|(_, bbox)| {
+                    config
+                        .structure_classes
+                        .iter()
+                        .any(|class| bbox.is_class(class))
+                })
+                .filter(|(_, bbox)| {
+                    let b = &bbox.bounds;
+                    b.min.z >= top - 1e-4
+                        && (b.min.x..=b.max.x).contains(&position.x)
+                        && (b.min.y..=b.max.y).contains(&position.y)
+                })
+                .min_by(|a, b| a.1.bounds.min.z.total_cmp(&b.1.bounds.min.z))
+                .map(|(i, _)| i);
+            let rod_length = structure.map(|i| scene.obstacles[i].bounds.min.z - top);
+            let obstructed_by = match rod_length {
+                Some(length) if length > 1e-4 => {
+                    let from = Vector3::new(position.x, position.y, top);
+                    let to = Vector3::new(position.x, position.y, top + length);
+                    scene
+                        .obstacles
+                        .iter()
+                        .enumerate()
+                        .filter(|&(i, bbox)| Some(i) != structure && bbox.is_obstacle())
+                        .filter(|(_, bbox)| {
+                            bbox.bounds
+                                .intersect_segment(from, to)
+                                .is_some_and(|(t0, t1)| (t1 - t0) * length > 1e-4)
+                        })
+                        .map(|(i, _)| i)
+                        .collect()
+                }
+                _ => vec![],
+            };
+            Hanger {
+                position,
+                segment,
+                offset,
+                structure,
+                rod_length,
+                obstructed_by,
+            }
+        })
+        .collect();
+
+    let mut unsupported: Vec<UnsupportedSpan> = vec![];
+    let mut anchored = 0.0;
+    let mut open: Option<UnsupportedSpan> = None;
+    for (i, hanger) in hangers.iter().enumerate() {
+        if hanger.is_reachable(config.max_rod_length) {
+            if let Some(mut span) = open.take() {
+                span.end = hanger.offset;
+                unsupported.push(span);
+            }
+            anchored = hanger.offset;
+        } else {
+            open.get_or_insert(UnsupportedSpan {
+                start: anchored,
+                end: anchored,
+                hangers: vec![],
+            })
+            .hangers
+            .push(i);
+        }
+    }
+    if let Some(mut span) = open {
+        span.end = offsets.last().copied().unwrap_or(0.0);
+        unsupported.push(span);
+    }
+
+    HangerPlan {
+        spacing,
+        hangers,
+        unsupported,
+    }
+}
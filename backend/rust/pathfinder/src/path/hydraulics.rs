@@ -0,0 +1,265 @@
+use std::collections::VecDeque;
+
+use crate::math::vector::Vector3;
+
+use super::{NetworkNodeKind, PathfindingPath, PipeNetwork};
+
+// Inside diameters of schedule 40 steel pipe from DN15 to DN300, in metres.
+pub const STANDARD_DIAMETERS: [f32; 14] = [
+    0.0158, 0.0209, 0.0266, 0.0351, 0.0409, 0.0525, 0.0627, 0.0779, 0.1023, 0.1282, 0.1541, 0.2027,
+    0.2545, 0.3033,
+];
+
+#[derive(Debug, Clone, Copy)]
+pub struct Fluid {
+    // kg/m³
+    pub density: f32,
+    // Dynamic viscosity in Pa·s.
+    pub viscosity: f32,
+}
+
+impl Fluid {
+    // Water at 20 °C.
+    pub fn water() -> Self {
+        Self {
+            density: 998.2,
+            viscosity: 1.002e-3,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct KFactors {
+    // Long radius 90° elbow; other angles are scaled linearly.
+    pub elbow_90: f32,
+    pub tee_run: f32,
+    pub tee_branch: f32,
+}
+
+impl Default for KFactors {
+    fn default() -> Self {
+        Self {
+            elbow_90: 0.75,
+            tee_run: 0.4,
+            tee_branch: 1.3,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct HydraulicSpec {
+    // m³/s
+    pub flow_rate: f32,
+    pub fluid: Fluid,
+    // Absolute roughness of the pipe wall in metres.
+    pub roughness: f32,
+    // Inside diameter in metres.
+    pub diameter: f32,
+    pub k: KFactors,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct SegmentLoss {
+    pub segment: usize,
+    pub length: f32,
+    // Pressure losses in Pa; `fittings` covers the fitting at the start of the segment.
+    pub friction: f32,
+    pub fittings: f32,
+}
+
+impl SegmentLoss {
+    pub fn total(&self) -> f32 {
+        self.friction + self.fittings
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct HydraulicReport {
+    pub diameter: f32,
+    pub velocity: f32,
+    pub reynolds: f32,
+    pub friction_factor: f32,
+    pub segments: Vec<SegmentLoss>,
+    pub total: f32,
+}
+
+// Darcy friction factor: exact for laminar flow, Colebrook–White otherwise, iterated from
+// the Swamee–Jain approximation.
+pub fn friction_factor(reynolds: f32, relative_roughness: f32) -> f32 {
+    if reynolds < 1e-6 {
+        return 0.0;
+    }
+    if reynolds < 2300.0 {
+        return 64.0 / reynolds;
+    }
+    let swamee = 0.25
+        / (relative_roughness / 3.7 + 5.74 / reynolds.powf(0.9))
+            .log10()
+            .powi(2);
+    let mut x = 1.0 / swamee.sqrt();
+    for _ in 0..20 {
+        x = -2.0 * (relative_roughness / 3.7 + 2.51 * x / reynolds).log10();
+    }
+    1.0 / (x * x)
+}
+
+fn deflection(incoming: Vector3, outgoing: Vector3) -> f32 {
+    (incoming.norm() * outgoing.norm())
+        .clamp(-1.0, 1.0)
+        .acos()
+        .to_degrees()
+}
+
+// Pressure drop along `path`. `tees` are the indices of points where the route passes
+// through a tee: straight through counts as the run, a turn as the branch.
+pub fn pressure_drop(
+    path: &PathfindingPath,
+    tees: &[usize],
+    spec: &HydraulicSpec,
+) -> HydraulicReport {
+    let area = std::f32::consts::PI * spec.diameter * spec.diameter / 4.0;
+    let velocity = spec.flow_rate / area;
+    let reynolds = spec.fluid.density * velocity * spec.diameter / spec.fluid.viscosity;
+    let f = friction_factor(reynolds, spec.roughness / spec.diameter);
+    let dynamic = 0.5 * spec.fluid.density * velocity * velocity;
+
+    let points = &path.points;
+    let segments: Vec<SegmentLoss> = points
+        .windows(2)
+        .enumerate()
+        .map(|(segment, pair)| {
+            let length = (pair[1] - pair[0]).length();
+            let angle = if segment > 0 {
+                deflection(pair[0] - points[segment - 1], pair[1] - pair[0])
+            } else {
+                0.0
+            };
+            let k = if tees.contains(&segment) {
+                if angle < 1.0 {
+                    spec.k.tee_run
+                } else {
+                    spec.k.tee_branch
+                }
+            } else if angle >= 1.0 {
+                spec.k.elbow_90 * angle / 90.0
+            } else {
+                0.0
+            };
+            SegmentLoss {
+                segment,
+                length,
+                friction: f * length / spec.diameter * dynamic,
+                fittings: k * dynamic,
+            }
+        })
+        .collect();
+    HydraulicReport {
+        diameter: spec.diameter,
+        velocity,
+        reynolds,
+        friction_factor: f,
+        total: segments.iter().map(|s| s.total()).sum(),
+        segments,
+    }
+}
+
+// Smallest of `diameters` whose pressure drop stays within `budget` Pa.
+pub fn size_for_budget(
+    path: &PathfindingPath,
+    tees: &[usize],
+    spec: &HydraulicSpec,
+    budget: f32,
+    diameters: &[f32],
+) -> Option<HydraulicReport> {
+    let mut diameters = diameters.to_vec();
+    diameters.sort_by(|a, b| a.total_cmp(b));
+    diameters.into_iter().find_map(|diameter| {
+        let report = pressure_drop(path, tees, &HydraulicSpec { diameter, ..*spec });
+        (report.total <= budget).then_some(report)
+    })
+}
+
+impl PipeNetwork {
+    // Route from the source to `node` through the network, with the indices of the points
+    // where it passes a tee or cross, ready for `pressure_drop`.
+    pub fn route_to(&self, node: usize) -> Option<(PathfindingPath, Vec<usize>)> {
+        // Terminals sharing a cell are reached through the node that carries its edges.
+        let cell = self.nodes.get(node)?.cell;
+        let node = self.nodes.iter().position(|other| other.cell == cell)?;
+        let source = self
+            .nodes
+            .iter()
+            .position(|node| node.kind == NetworkNodeKind::Source)?;
+        let mut came_from: Vec<Option<usize>> = vec![None; self.nodes.len()];
+        let mut queue = VecDeque::from([source]);
+        let mut seen = vec![false; self.nodes.len()];
+        seen[source] = true;
+        while let Some(n) = queue.pop_front() {
+            for (e, edge) in self.edges.iter().enumerate() {
+                let next = match (edge.from == n, edge.to == n) {
+                    (true, _) => edge.to,
+                    (_, true) => edge.from,
+                    _ => continue,
+                };
+                if !seen[next] {
+                    seen[next] = true;
+                    came_from[next] = Some(e);
+                    queue.push_back(next);
+                }
+            }
+        }
+        if !seen.get(node).copied().unwrap_or(false) {
+            return None;
+        }
+
+        let mut edges = vec![];
+        let mut current = node;
+        while let Some(e) = came_from[current] {
+            edges.push(e);
+            let edge = &self.edges[e];
+            current = if edge.to == current {
+                edge.from
+            } else {
+                edge.to
+            };
+        }
+        edges.reverse();
+
+        let mut points: Vec<Vector3> = vec![self.nodes[source].position];
+        let mut tees = vec![];
+        let mut at = source;
+        for e in edges {
+            let edge = &self.edges[e];
+            let mut edge_points = edge.path.points.clone();
+            if edge.from != at {
+                edge_points.reverse();
+            }
+            if matches!(
+                self.nodes[at].kind,
+                NetworkNodeKind::Tee | NetworkNodeKind::Cross
+            ) {
+                tees.push(points.len() - 1);
+            }
+            points.extend(edge_points.into_iter().skip(1));
+            at = if edge.from == at { edge.to } else { edge.from };
+        }
+
+        // Drop points in the middle of straight runs, keeping the tees in place.
+        let mut compact: Vec<Vector3> = vec![];
+        let mut compact_tees = vec![];
+        for (i, &point) in points.iter().enumerate() {
+            let is_tee = tees.contains(&i);
+            if i > 0 && i + 1 < points.len() && !is_tee {
+                let previous = *compact.last().unwrap();
+                if deflection(point - previous, points[i + 1] - point) < 1e-2 {
+                    continue;
+                }
+            }
+            if is_tee {
+                compact_tees.push(compact.len());
+            }
+            compact.push(point);
+        }
+        Some((PathfindingPath { points: compact }, compact_tees))
+    }
+}
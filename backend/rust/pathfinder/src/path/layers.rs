@@ -0,0 +1,169 @@
+use std::collections::HashMap;
+
+use crate::{math::vector::Vector3, scene::Scene};
+
+use super::{Cell, Grid};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LayerReference {
+    // Underside of the slab above.
+    SlabSoffit,
+    // Top of the suspended ceiling below.
+    Ceiling,
+}
+
+// Height band of one system, as offsets from the reference surface, negative below it.
+#[derive(Debug, Clone)]
+pub struct LayerBand {
+    pub system: String,
+    pub reference: LayerReference,
+    pub bottom: f32,
+    pub top: f32,
+}
+
+#[derive(Debug, Clone)]
+pub struct LayerSpec {
+    pub bands: Vec<LayerBand>,
+    // Extra cost per cell routed outside the band.
+    pub violation_cost: f32,
+}
+
+impl Default for LayerSpec {
+    // Drainage lowest, then heating, ventilation and electrical closest to the slab.
+    fn default() -> Self {
+        let bands = [
+            ("Drainage", -1.0, -0.75),
+            ("Heating", -0.75, -0.55),
+            ("Ventilation", -0.55, -0.25),
+            ("Electrical", -0.25, 0.0),
+        ]
+        .into_iter()
+        .map(|(system, bottom, top)| LayerBand {
+            system: system.to_string(),
+            reference: LayerReference::SlabSoffit,
+            bottom,
+            top,
+        })
+        .collect();
+        Self {
+            bands,
+            violation_cost: 5.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LayerViolation {
+    pub pipe: String,
+    pub system: String,
+    pub from: Vector3,
+    pub to: Vector3,
+    pub cells: usize,
+}
+
+// Per system, which grid cells lie inside its band.
+pub struct LayerField {
+    masks: HashMap<String, Vec<bool>>,
+    pub violation_cost: f32,
+}
+
+impl LayerField {
+    pub fn build(grid: &Grid, scene: &Scene, spec: &LayerSpec) -> Self {
+        let covers = |bounds: &crate::scene::Bounds, p: Vector3| {
+            (bounds.min.x..=bounds.max.x).contains(&p.x)
+                && (bounds.min.y..=bounds.max.y).contains(&p.y)
+        };
+        let slabs: Vec<_> = scene
+            .obstacles
+            .iter()
+            .filter(|bbox| bbox.is_class("IfcSlab"))
+            .collect();
+        let ceilings: Vec<_> = scene
+            .obstacles
+            .iter()
+            .filter(|bbox| bbox.is_class("IfcCovering"))
+            .collect();
+        let reference = |reference: LayerReference, p: Vector3| match reference {
+            LayerReference::SlabSoffit => slabs
+                .iter()
+                .filter(|slab| covers(&slab.bounds, p) && slab.bounds.min.z >= p.z)
+                .map(|slab| slab.bounds.min.z)
+                .min_by(|a, b| a.total_cmp(b)),
+            LayerReference::Ceiling => ceilings
+                .iter()
+                .filter(|ceiling| covers(&ceiling.bounds, p) && ceiling.bounds.max.z <= p.z)
+                .map(|ceiling| ceiling.bounds.max.z)
+                .max_by(|a, b| a.total_cmp(b)),
+        };
+
+        let mut masks = HashMap::new();
+        for band in spec.bands.iter() {
+            let mask = (0..grid.len())
+                .map(|i| {
+                    let p = grid.cell_center(grid.cell(i));
+                    reference(band.reference, p).is_some_and(|level| {
+                        (level + band.bottom..=level + band.top).contains(&p.z)
+                    })
+                })
+                .collect();
+            masks.insert(band.system.clone(), mask);
+        }
+        Self {
+            masks,
+            violation_cost: spec.violation_cost,
+        }
+    }
+
+    pub fn mask(&self, system: &str) -> Option<&[bool]> {
+        self.masks.get(system).map(|mask| mask.as_slice())
+    }
+
+    // Systems without a band are allowed anywhere.
+    pub fn in_band(&self, grid: &Grid, system: &str, cell: Cell) -> bool {
+        self.mask(system).is_none_or(|mask| mask[grid.index(cell)])
+    }
+
+    // Stretches of `cells` outside the band of `system`, ignoring vertical drops straight
+    // above or below either end.
+    pub fn violations(
+        &self,
+        grid: &Grid,
+        pipe: &str,
+        system: &str,
+        cells: &[Cell],
+    ) -> Vec<LayerViolation> {
+        let (Some(&first), Some(&last)) = (cells.first(), cells.last()) else {
+            return vec![];
+        };
+        let exempt = |cell: Cell| is_drop(first, cell) || is_drop(last, cell);
+        let mut violations: Vec<LayerViolation> = vec![];
+        let mut open = false;
+        for &cell in cells.iter() {
+            if self.in_band(grid, system, cell) || exempt(cell) {
+                open = false;
+                continue;
+            }
+            let position = grid.cell_center(cell);
+            match violations.last_mut() {
+                Some(violation) if open => {
+                    violation.to = position;
+                    violation.cells += 1;
+                }
+                _ => violations.push(LayerViolation {
+                    pipe: pipe.to_string(),
+                    system: system.to_string(),
+                    from: position,
+                    to: position,
+                    cells: 1,
+                }),
+            }
+            open = true;
+        }
+        violations
+    }
+}
+
+// `cell` lies in the vertical column of `endpoint`.
+pub(crate) fn is_drop(endpoint: Cell, cell: Cell) -> bool {
+    endpoint.x == cell.x && endpoint.y == cell.y
+}
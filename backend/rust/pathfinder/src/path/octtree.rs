@@ -1,6 +1,7 @@
 use crate::{
     math::{matrix::Matrix4, vector::Vector3},
-    scene::{BBox, Bounds, Scene},
+    scene::{BBox, Bounds, Scene, SceneChange},
+    utility::GenError,
 };
 
 use super::VoxelStrategy;
@@ -13,6 +14,8 @@ struct Node {
 
 pub struct Octtree {
     root: Node,
+    min_depth: u32,
+    max_depth: u32,
 }
 
 impl Node {
@@ -39,6 +42,42 @@ impl Node {
         }
     }
 
+    // Rebuilds the nodes overlapping `region` from the current obstacles.
+    fn refresh<S: VoxelStrategy>(
+        &mut self,
+        region: &Bounds,
+        obstacles: &[BBox],
+        min_depth: u32,
+        max_depth: u32,
+    ) {
+        // Inclusive, so that flat regions and ones lying on a node face still count.
+        let touches = self.bounds.min.x <= region.max.x
+            && region.min.x <= self.bounds.max.x
+            && self.bounds.min.y <= region.max.y
+            && region.min.y <= self.bounds.max.y
+            && self.bounds.min.z <= region.max.z
+            && region.min.z <= self.bounds.max.z;
+        if !touches {
+            return;
+        }
+        let needed = self.depth < min_depth
+            || (self.depth < max_depth
+                && obstacles
+                    .iter()
+                    .any(|bbox| S::is_valid(&self.bounds, &bbox.bounds)));
+        if !needed {
+            self.child.clear();
+        } else if self.child.is_empty() {
+            for bbox in obstacles {
+                self.insert::<S>(bbox, min_depth, max_depth);
+            }
+        } else {
+            for child in &mut self.child {
+                child.refresh::<S>(region, obstacles, min_depth, max_depth);
+            }
+        }
+    }
+
     fn octants(&self) -> impl Iterator<Item = Bounds> {
         let min = self.bounds.min;
         let max = self.bounds.max;
@@ -102,7 +141,31 @@ impl Octtree {
         for bbox in scene.get_obstacles().iter() {
             root.insert::<S>(bbox, min_depth, max_depth);
         }
-        Self { root }
+        Self {
+            root,
+            min_depth,
+            max_depth,
+        }
+    }
+
+    // Follows an edit of `scene`, which must already have been applied. Fails, leaving the
+    // tree untouched, when the element now reaches outside it.
+    pub fn apply_change<S: VoxelStrategy>(
+        &mut self,
+        scene: &Scene,
+        change: &SceneChange,
+    ) -> GenError<()> {
+        if change.leaves(&self.root.bounds) {
+            return Err("changed element reaches outside the octree, which must be rebuilt".into());
+        }
+        let obstacles = scene.get_obstacles();
+        self.root.refresh::<S>(
+            &change.dirty_region(),
+            &obstacles,
+            self.min_depth,
+            self.max_depth,
+        );
+        Ok(())
     }
 
     pub fn get_bounds_instances(&self) -> Vec<Matrix4> {
@@ -0,0 +1,321 @@
+use std::{
+    cmp::Ordering,
+    collections::{BinaryHeap, HashMap},
+};
+
+use serde::Serialize;
+
+use crate::{math::vector::Vector3, scene::Scene, utility::GenError};
+
+use super::{opposite, Cell, Grid, PathfindingPath, PenetrationPolicy, DIRECTIONS};
+
+#[derive(Debug, Clone, Copy)]
+pub struct ParetoConfig {
+    // How far beyond the box spanned by the two endpoints routes may wander.
+    pub margin: f32,
+    // Labels created before the search gives up, returning the front found so far.
+    pub max_labels: usize,
+}
+
+impl Default for ParetoConfig {
+    fn default() -> Self {
+        Self {
+            margin: 2.0,
+            max_labels: 2_000_000,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Objectives {
+    pub length: f32,
+    pub bends: usize,
+    pub penetrations: usize,
+    // Total vertical travel.
+    pub elevation: f32,
+}
+
+#[derive(Debug, Clone)]
+pub struct ParetoRoute {
+    pub cells: Vec<Cell>,
+    pub path: PathfindingPath,
+    pub objectives: Objectives,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ParetoFront {
+    // Ordered by length.
+    pub routes: Vec<ParetoRoute>,
+    // The label budget ran out, so the front may be incomplete.
+    pub truncated: bool,
+}
+
+#[derive(Serialize)]
+struct RouteExport<'a> {
+    points: Vec<[f32; 3]>,
+    #[serde(flatten)]
+    objectives: &'a Objectives,
+}
+
+impl ParetoFront {
+    pub fn to_json(&self) -> GenError<String> {
+        let routes: Vec<RouteExport> = self
+            .routes
+            .iter()
+            .map(|route| RouteExport {
+                points: route.path.points.iter().map(|p| [p.x, p.y, p.z]).collect(),
+                objectives: &route.objectives,
+            })
+            .collect();
+        Ok(serde_json::to_string_pretty(&routes)?)
+    }
+}
+
+// Steps, bends, penetrations and vertical steps; whole numbers keep dominance exact.
+type Costs = [u32; 4];
+
+fn dominates(a: &Costs, b: &Costs) -> bool {
+    a.iter().zip(b.iter()).all(|(x, y)| x <= y)
+}
+
+struct Label {
+    cell: Cell,
+    dir: Option<usize>,
+    costs: Costs,
+    parent: Option<usize>,
+    alive: bool,
+}
+
+struct Open {
+    f: Costs,
+    label: usize,
+}
+
+impl PartialEq for Open {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Open {}
+
+impl PartialOrd for Open {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Open {
+    // Lexicographic on the estimated totals, smallest first.
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.f.cmp(&self.f)
+    }
+}
+
+impl Grid {
+    // Elements entered when stepping into `to`, `None` if one of them may not be crossed.
+    fn crossings(
+        &self,
+        scene: &Scene,
+        policy: &PenetrationPolicy,
+        from: Cell,
+        to: Cell,
+    ) -> Option<u32> {
+        if self.is_free(to) {
+            return Some(0);
+        }
+        let mut entered = 0;
+        for element in self.occupants(to) {
+            policy.cost(&scene.obstacles[*element])?;
+            if !self.occupants(from).contains(element) {
+                entered += 1;
+            }
+        }
+        Some(entered)
+    }
+
+    // Multi-objective label-setting search (NAMOA*) for every route between two cells that
+    // no other route beats on length, bends, penetrations and vertical travel at once.
+    pub fn pareto_routes(
+        &self,
+        scene: &Scene,
+        policy: &PenetrationPolicy,
+        start: Cell,
+        goal: Cell,
+        config: &ParetoConfig,
+    ) -> ParetoFront {
+        let margin = (config.margin / self.cell_size()).ceil() as usize;
+        let extent = self.extent();
+        let low = |a: usize, b: usize| a.min(b).saturating_sub(margin);
+        let high = |a: usize, b: usize, limit: usize| (a.max(b) + margin).min(limit - 1);
+        let window = (
+            Cell::new(
+                low(start.x, goal.x),
+                low(start.y, goal.y),
+                low(start.z, goal.z),
+            ),
+            Cell::new(
+                high(start.x, goal.x, extent.x),
+                high(start.y, goal.y, extent.y),
+                high(start.z, goal.z, extent.z),
+            ),
+        );
+        let inside = |c: Cell| {
+            (window.0.x..=window.1.x).contains(&c.x)
+                && (window.0.y..=window.1.y).contains(&c.y)
+                && (window.0.z..=window.1.z).contains(&c.z)
+        };
+
+        // Admissible estimate of the remaining costs.
+        let estimate = |cell: Cell, dir: Option<usize>| -> Costs {
+            let deltas = [
+                goal.x as i64 - cell.x as i64,
+                goal.y as i64 - cell.y as i64,
+                goal.z as i64 - cell.z as i64,
+            ];
+            let axes = deltas.iter().filter(|d| **d != 0).count() as u32;
+            let aligned = dir.is_none_or(|dir| {
+                let delta = deltas[dir / 2];
+                delta != 0 && (delta > 0) == (dir % 2 == 0)
+            });
+            let bends = if aligned {
+                axes.saturating_sub(1)
+            } else {
+                axes
+            };
+            [
+                deltas.iter().map(|d| d.unsigned_abs() as u32).sum(),
+                bends,
+                0,
+                deltas[2].unsigned_abs() as u32,
+            ]
+        };
+        let add = |a: Costs, b: Costs| [a[0] + b[0], a[1] + b[1], a[2] + b[2], a[3] + b[3]];
+
+        let mut labels = vec![Label {
+            cell: start,
+            dir: None,
+            costs: [0; 4],
+            parent: None,
+            alive: true,
+        }];
+        let mut at: HashMap<(Cell, Option<usize>), Vec<usize>> = HashMap::new();
+        at.insert((start, None), vec![0]);
+        let mut open = BinaryHeap::from([Open {
+            f: estimate(start, None),
+            label: 0,
+        }]);
+        let mut found: Vec<usize> = vec![];
+        let mut front = ParetoFront::default();
+
+        while let Some(Open { f, label }) = open.pop() {
+            if !labels[label].alive || found.iter().any(|&g| dominates(&labels[g].costs, &f)) {
+                continue;
+            }
+            let (cell, dir, costs) = (labels[label].cell, labels[label].dir, labels[label].costs);
+            if cell == goal {
+                found.push(label);
+                continue;
+            }
+            for next_dir in 0..DIRECTIONS.len() {
+                let Some(next) = self.neighbor(cell, next_dir).filter(|&c| inside(c)) else {
+                    continue;
+                };
+                if dir.is_some_and(|dir| dir == opposite(next_dir)) {
+                    continue;
+                }
+                let Some(entered) = self.crossings(scene, policy, cell, next) else {
+                    continue;
+                };
+                let step = [
+                    1,
+                    u32::from(dir.is_some_and(|dir| dir != next_dir)),
+                    entered,
+                    u32::from(next_dir / 2 == 2),
+                ];
+                let g = add(costs, step);
+                let f = add(g, estimate(next, Some(next_dir)));
+                if found.iter().any(|&goal| dominates(&labels[goal].costs, &f)) {
+                    continue;
+                }
+                let existing = at.entry((next, Some(next_dir))).or_default();
+                if existing.iter().any(|&l| dominates(&labels[l].costs, &g)) {
+                    continue;
+                }
+                existing.retain(|&l| {
+                    let beaten = dominates(&g, &labels[l].costs);
+                    if beaten {
+                        labels[l].alive = false;
+                    }
+                    !beaten
+                });
+                if labels.len() >= config.max_labels {
+                    front.truncated = true;
+                    open.clear();
+                    break;
+                }
+                existing.push(labels.len());
+                open.push(Open {
+                    f,
+                    label: labels.len(),
+                });
+                labels.push(Label {
+                    cell: next,
+                    dir: Some(next_dir),
+                    costs: g,
+                    parent: Some(label),
+                    alive: true,
+                });
+            }
+        }
+
+        // Goal labels arriving from different directions may still dominate each other.
+        let mut costs: Vec<(usize, Costs)> = found.iter().map(|&l| (l, labels[l].costs)).collect();
+        costs.sort_by_key(|&(_, costs)| costs);
+        costs.dedup_by(|a, b| a.1 == b.1);
+        let front_costs: Vec<(usize, Costs)> = costs
+            .iter()
+            .filter(|(_, c)| !costs.iter().any(|(_, d)| d != c && dominates(d, c)))
+            .copied()
+            .collect();
+        let cell_size = self.cell_size();
+        for (label, costs) in front_costs {
+            let mut cells = vec![];
+            let mut current = Some(label);
+            while let Some(l) = current {
+                cells.push(labels[l].cell);
+                current = labels[l].parent;
+            }
+            cells.reverse();
+            front.routes.push(ParetoRoute {
+                path: self.cells_to_path(&cells).merge_collinear(1e-4),
+                cells,
+                objectives: Objectives {
+                    length: costs[0] as f32 * cell_size,
+                    bends: costs[1] as usize,
+                    penetrations: costs[2] as usize,
+                    elevation: costs[3] as f32 * cell_size,
+                },
+            });
+        }
+        front
+            .routes
+            .sort_by(|a, b| a.objectives.length.total_cmp(&b.objectives.length));
+        front
+    }
+}
+
+// Pareto front of routes between two points; the endpoints are snapped to grid cells.
+pub fn pareto_front(
+    grid: &Grid,
+    scene: &Scene,
+    start: Vector3,
+    goal: Vector3,
+    policy: &PenetrationPolicy,
+    config: &ParetoConfig,
+) -> ParetoFront {
+    match (grid.cell_at(start), grid.cell_at(goal)) {
+        (Some(start), Some(goal)) => grid.pareto_routes(scene, policy, start, goal, config),
+        _ => ParetoFront::default(),
+    }
+}
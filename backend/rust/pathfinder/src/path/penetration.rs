@@ -0,0 +1,167 @@
+use std::collections::HashMap;
+
+use crate::{
+    math::vector::Vector3,
+    scene::{BBox, Scene},
+};
+
+use super::{direction_between, Cell, CostModel, Grid, PathfindingPath, SearchParams, DIRECTIONS};
+
+#[derive(Debug, Clone)]
+pub struct PenetrationPolicy {
+    pub class_costs: HashMap<String, f32>,
+    // Cost for elements flagged `penetrable` whose class has no explicit cost.
+    pub default_cost: f32,
+    // Extra cost for every step taken inside an element other than along its thin axis.
+    pub oblique_cost: f32,
+    // Annular gap between the pipe and the sleeve, on each side.
+    pub sleeve_gap: f32,
+}
+
+impl Default for PenetrationPolicy {
+    fn default() -> Self {
+        let class_costs = [
+            ("IfcWall", 10.0),
+            ("IfcWallStandardCase", 10.0),
+            ("IfcSlab", 25.0),
+            ("IfcCovering", 2.0),
+        ]
+        .into_iter()
+        .map(|(class, cost)| (class.to_string(), cost))
+        .collect();
+        Self {
+            class_costs,
+            default_cost: 10.0,
+            oblique_cost: 5.0,
+            sleeve_gap: 0.025,
+        }
+    }
+}
+
+impl PenetrationPolicy {
+    pub fn cost(&self, bbox: &BBox) -> Option<f32> {
+        // An explicit flag overrides the class, e.g. a fire wall or post-tensioned slab.
+        if bbox.penetrable == Some(false) {
+            return None;
+        }
+        if let Some(cost) = bbox
+            .class
+            .as_ref()
+            .and_then(|class| self.class_costs.get(class))
+        {
+            return Some(*cost);
+        }
+        (bbox.penetrable == Some(true)).then_some(self.default_cost)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Penetration {
+    // Index into `Scene::obstacles`.
+    pub element: usize,
+    pub class: Option<String>,
+    pub segment: usize,
+    pub position: Vector3,
+    pub sleeve_diameter: f32,
+    pub sleeve_length: f32,
+    // Angle between the pipe and the element plane in degrees, 90 for a perpendicular crossing.
+    pub crossing_angle: f32,
+}
+
+pub struct Penetrating<'a> {
+    pub scene: &'a Scene,
+    pub policy: &'a PenetrationPolicy,
+}
+
+impl CostModel for Penetrating<'_> {
+    fn step_cost(&self, grid: &Grid, from: Cell, to: Cell) -> Option<f32> {
+        if grid.is_free(to) {
+            return Some(grid.cell_size());
+        }
+        let axis = direction_between(from, to)? / 2;
+        let mut cost = grid.cell_size();
+        for &element in grid.occupants(to) {
+            let bbox = &self.scene.obstacles[element];
+            let penetration = self.policy.cost(bbox)?;
+            if !grid.occupants(from).contains(&element) {
+                cost += penetration;
+            }
+            if bbox.thin_axis() != axis {
+                cost += self.policy.oblique_cost;
+            }
+        }
+        Some(cost)
+    }
+}
+
+pub fn find_penetrations(
+    scene: &Scene,
+    path: &PathfindingPath,
+    diameter: f32,
+    policy: &PenetrationPolicy,
+) -> Vec<Penetration> {
+    let offsets = path.offsets();
+    let mut penetrations = vec![];
+    for (element, bbox) in scene.obstacles.iter().enumerate() {
+        if !bbox.is_obstacle() || policy.cost(bbox).is_none() {
+            continue;
+        }
+        // Stretches of the path inside the element, as distances along the path.
+        let mut inside: Vec<(f32, f32)> = vec![];
+        for (segment, pair) in path.points.windows(2).enumerate() {
+            let Some((t0, t1)) = bbox.bounds.intersect_segment(pair[0], pair[1]) else {
+                continue;
+            };
+            let length = offsets[segment + 1] - offsets[segment];
+            let (start, end) = (
+                offsets[segment] + t0 * length,
+                offsets[segment] + t1 * length,
+            );
+            match inside.last_mut() {
+                Some(last) if start - last.1 < 1e-4 => last.1 = end,
+                _ => inside.push((start, end)),
+            }
+        }
+        let [nx, ny, nz] = DIRECTIONS[2 * bbox.thin_axis()];
+        let normal = Vector3::new(nx as f32, ny as f32, nz as f32);
+        for (start, end) in inside {
+            if end - start < 1e-4 {
+                continue;
+            }
+            let (segment, position) = path.point_at((start + end) / 2.0);
+            let dir = (path.points[segment + 1] - path.points[segment]).norm();
+            let sin = (dir * normal).abs().clamp(0.0, 1.0);
+            penetrations.push(Penetration {
+                element,
+                class: bbox.class.clone(),
+                segment,
+                position,
+                sleeve_diameter: diameter + 2.0 * policy.sleeve_gap,
+                sleeve_length: end - start,
+                crossing_angle: sin.asin().to_degrees(),
+            });
+        }
+    }
+    penetrations.sort_by_key(|penetration| (penetration.segment, penetration.element));
+    penetrations
+}
+
+impl Grid {
+    pub fn search_with_penetrations(
+        &self,
+        scene: &Scene,
+        start: Vector3,
+        goal: Vector3,
+        diameter: f32,
+        policy: &PenetrationPolicy,
+        params: &SearchParams,
+    ) -> Option<(PathfindingPath, Vec<Penetration>)> {
+        let start = self.cell_at(start)?;
+        let goal = self.cell_at(goal)?;
+        let cost = Penetrating { scene, policy };
+        let cells = self.search(&[start], &[goal], &cost, params)?;
+        let path = self.cells_to_path(&cells);
+        let penetrations = find_penetrations(scene, &path, diameter, policy);
+        Some((path, penetrations))
+    }
+}
@@ -0,0 +1,279 @@
+use std::collections::HashSet;
+
+use crate::{math::vector::Vector3, scene::Scene};
+
+use super::PathfindingPath;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FittingAngles {
+    // Only 90° elbows, every segment axis-aligned.
+    Right,
+    // 45° and 90° elbows, segments axis-aligned or diagonal within a coordinate plane.
+    RightAndHalf,
+}
+
+impl FittingAngles {
+    fn allows_direction(&self, dir: Vector3) -> bool {
+        let mut components = [dir.x.abs(), dir.y.abs(), dir.z.abs()];
+        components.sort_by(|a, b| b.total_cmp(a));
+        let axis = (components[0] - 1.0).abs() < 1e-3;
+        let diagonal = (components[0] - components[1]).abs() < 1e-3 && components[2] < 1e-3;
+        match self {
+            FittingAngles::Right => axis,
+            FittingAngles::RightAndHalf => axis || diagonal,
+        }
+    }
+
+    fn allows_bend(&self, incoming: Vector3, outgoing: Vector3) -> bool {
+        let angle = (incoming * outgoing).clamp(-1.0, 1.0).acos().to_degrees();
+        let allowed: &[f32] = match self {
+            FittingAngles::Right => &[0.0, 90.0],
+            FittingAngles::RightAndHalf => &[0.0, 45.0, 90.0],
+        };
+        allowed.iter().any(|&a| (angle - a).abs() < 1.0)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct PostProcessConfig {
+    // Pipe radius plus clearance kept from every obstacle.
+    pub radius: f32,
+    pub angles: FittingAngles,
+    pub tolerance: f32,
+}
+
+impl Default for PostProcessConfig {
+    fn default() -> Self {
+        Self {
+            radius: 0.05,
+            angles: FittingAngles::Right,
+            tolerance: 1e-4,
+        }
+    }
+}
+
+// Indices of the obstacles that the pipe swept along `a`-`b` would touch.
+pub fn segment_hits(scene: &Scene, a: Vector3, b: Vector3, radius: f32) -> Vec<usize> {
+    scene
+        .obstacles
+        .iter()
+        .enumerate()
+        .filter(|(_, obstacle)| obstacle.is_obstacle())
+        .filter(|(_, obstacle)| {
+            obstacle
+                .bounds
+                .expand(radius)
+                .intersect_segment(a, b)
+                .is_some_and(|(t0, t1)| (t1 - t0) * (b - a).length() > 1e-5)
+        })
+        .map(|(i, _)| i)
+        .collect()
+}
+
+fn path_hits(scene: &Scene, path: &PathfindingPath, radius: f32) -> HashSet<usize> {
+    path.points
+        .windows(2)
+        .flat_map(|pair| segment_hits(scene, pair[0], pair[1], radius))
+        .collect()
+}
+
+fn direction(a: Vector3, b: Vector3) -> Vector3 {
+    (b - a).norm()
+}
+
+impl PathfindingPath {
+    pub fn merge_collinear(&self, tolerance: f32) -> PathfindingPath {
+        let mut points: Vec<Vector3> = vec![];
+        for &point in self.points.iter() {
+            if points
+                .last()
+                .is_some_and(|&last| (point - last).length() < tolerance)
+            {
+                continue;
+            }
+            if points.len() >= 2 {
+                let a = points[points.len() - 2];
+                let b = points[points.len() - 1];
+                if (direction(a, b) * direction(b, point)) > 1.0 - tolerance {
+                    points.pop();
+                }
+            }
+            points.push(point);
+        }
+        PathfindingPath { points }
+    }
+}
+
+// Polylines from `a` to `b` (excluding `a`) made of segments the fittings allow, fewest
+// bends first.
+fn snap_candidates(a: Vector3, b: Vector3, angles: FittingAngles) -> Vec<Vec<Vector3>> {
+    let d = b - a;
+    if d.length() < 1e-6 {
+        return vec![];
+    }
+    if angles.allows_direction(d.norm()) {
+        return vec![vec![b]];
+    }
+    let axis_moves = [
+        Vector3::new(d.x, 0.0, 0.0),
+        Vector3::new(0.0, d.y, 0.0),
+        Vector3::new(0.0, 0.0, d.z),
+    ];
+    let mut move_sets: Vec<Vec<Vector3>> = vec![axis_moves.to_vec()];
+    if angles == FittingAngles::RightAndHalf {
+        let components = [d.x, d.y, d.z];
+        for (u, v, w) in [(0, 1, 2), (0, 2, 1), (1, 2, 0)] {
+            let m = components[u].abs().min(components[v].abs());
+            if m < 1e-6 {
+                continue;
+            }
+            let diagonal = m * (axis_moves[u].norm() + axis_moves[v].norm());
+            move_sets.push(vec![
+                diagonal,
+                axis_moves[u] + axis_moves[v] - diagonal,
+                axis_moves[w],
+            ]);
+        }
+    }
+
+    let mut candidates: Vec<Vec<Vector3>> = vec![];
+    for moves in move_sets {
+        let moves: Vec<Vector3> = moves.into_iter().filter(|m| m.length() > 1e-6).collect();
+        for order in permutations(moves.len()) {
+            let mut point = a;
+            let mut polyline = vec![];
+            for &k in order.iter() {
+                point = point + moves[k];
+                polyline.push(point);
+            }
+            *polyline.last_mut().unwrap() = b;
+            candidates.push(polyline);
+        }
+    }
+    candidates.sort_by_key(|polyline| polyline.len());
+    candidates
+}
+
+fn permutations(n: usize) -> Vec<Vec<usize>> {
+    if n == 0 {
+        return vec![vec![]];
+    }
+    let mut result = vec![];
+    for rest in permutations(n - 1) {
+        for position in 0..=rest.len() {
+            let mut order = rest.clone();
+            order.insert(position, n - 1);
+            result.push(order);
+        }
+    }
+    result
+}
+
+// First candidate that stays clear of the scene and bends only at allowed angles.
+fn clear_candidate(
+    scene: &Scene,
+    from: Vector3,
+    to: Vector3,
+    incoming: Option<Vector3>,
+    config: &PostProcessConfig,
+) -> Option<Vec<Vector3>> {
+    snap_candidates(from, to, config.angles)
+        .into_iter()
+        .find(|polyline| {
+            let mut previous = from;
+            let mut incoming = incoming;
+            for &point in polyline.iter() {
+                let dir = direction(previous, point);
+                if incoming.is_some_and(|incoming| !config.angles.allows_bend(incoming, dir))
+                    || !segment_hits(scene, previous, point, config.radius).is_empty()
+                {
+                    return false;
+                }
+                incoming = Some(dir);
+                previous = point;
+            }
+            true
+        })
+}
+
+// Greedy string pulling: from every anchor jump to the furthest later point that can be
+// reached by a clear polyline of allowed fittings.
+pub fn string_pull(
+    scene: &Scene,
+    path: &PathfindingPath,
+    config: &PostProcessConfig,
+) -> PathfindingPath {
+    let points = &path.points;
+    if points.len() < 3 {
+        return path.clone();
+    }
+    let mut pulled = vec![points[0]];
+    let mut incoming: Option<Vector3> = None;
+    let mut i = 0;
+    while i + 1 < points.len() {
+        let mut next = None;
+        for j in (i + 2..points.len()).rev() {
+            if let Some(polyline) = clear_candidate(scene, points[i], points[j], incoming, config) {
+                next = Some((j, polyline));
+                break;
+            }
+        }
+        let (j, polyline) = next.unwrap_or((i + 1, vec![points[i + 1]]));
+        let mut previous = *pulled.last().unwrap();
+        for point in polyline {
+            incoming = Some(direction(previous, point));
+            pulled.push(point);
+            previous = point;
+        }
+        i = j;
+    }
+    PathfindingPath { points: pulled }
+}
+
+// Replaces every segment at a disallowed angle by a clear polyline of allowed fittings,
+// leaving it untouched when no such polyline exists.
+pub fn snap_to_fittings(
+    scene: &Scene,
+    path: &PathfindingPath,
+    config: &PostProcessConfig,
+) -> PathfindingPath {
+    let mut points = path.points.iter().copied();
+    let Some(first) = points.next() else {
+        return PathfindingPath { points: vec![] };
+    };
+    let mut snapped = vec![first];
+    let mut incoming: Option<Vector3> = None;
+    for point in points {
+        let previous = *snapped.last().unwrap();
+        let polyline = clear_candidate(scene, previous, point, incoming, config)
+            .unwrap_or_else(|| vec![point]);
+        let mut previous = previous;
+        for point in polyline {
+            incoming = Some(direction(previous, point));
+            snapped.push(point);
+            previous = point;
+        }
+    }
+    PathfindingPath { points: snapped }
+}
+
+// Runs every post-processing step in turn. Each step is kept only if the result touches no
+// obstacle that the previous result did not already touch.
+pub fn post_process(
+    scene: &Scene,
+    path: &PathfindingPath,
+    config: &PostProcessConfig,
+) -> PathfindingPath {
+    let verify = |before: &PathfindingPath, after: PathfindingPath| {
+        let hits = path_hits(scene, &after, config.radius);
+        if hits.is_subset(&path_hits(scene, before, config.radius)) {
+            after
+        } else {
+            before.clone()
+        }
+    };
+    let merged = verify(path, path.merge_collinear(config.tolerance));
+    let pulled = verify(&merged, string_pull(scene, &merged, config));
+    let snapped = verify(&pulled, snap_to_fittings(scene, &pulled, config));
+    verify(&snapped, snapped.merge_collinear(config.tolerance))
+}
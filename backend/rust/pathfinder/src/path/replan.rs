@@ -0,0 +1,311 @@
+use std::{
+    cmp::Ordering,
+    collections::{BinaryHeap, HashMap, HashSet},
+};
+
+use super::{direction_between, Cell, CostModel, Grid, DIRECTIONS};
+
+type Key = (f32, f32);
+
+struct Entry {
+    key: Key,
+    cell: Cell,
+}
+
+impl PartialEq for Entry {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Entry {}
+
+impl PartialOrd for Entry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Entry {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .key
+            .0
+            .total_cmp(&self.key.0)
+            .then_with(|| other.key.1.total_cmp(&self.key.1))
+    }
+}
+
+// D* Lite between two fixed cells. The search runs backwards from the goal, so after the
+// grid changes only the cells whose costs changed, and those depending on them, are
+// expanded again.
+pub struct DStarLite {
+    start: Cell,
+    goal: Cell,
+    g: HashMap<Cell, f32>,
+    rhs: HashMap<Cell, f32>,
+    open: BinaryHeap<Entry>,
+    // Current key of every queued cell; heap entries with another key are stale.
+    queued: HashMap<Cell, Key>,
+    pub max_expansions: usize,
+}
+
+impl DStarLite {
+    pub fn new(start: Cell, goal: Cell, max_expansions: usize) -> Self {
+        let mut planner = Self {
+            start,
+            goal,
+            g: HashMap::new(),
+            rhs: HashMap::from([(goal, 0.0)]),
+            open: BinaryHeap::new(),
+            queued: HashMap::new(),
+            max_expansions,
+        };
+        planner.push(goal, (0.0, 0.0));
+        planner
+    }
+
+    fn g(&self, cell: Cell) -> f32 {
+        *self.g.get(&cell).unwrap_or(&f32::INFINITY)
+    }
+
+    fn rhs(&self, cell: Cell) -> f32 {
+        *self.rhs.get(&cell).unwrap_or(&f32::INFINITY)
+    }
+
+    fn heuristic<C: CostModel + ?Sized>(&self, grid: &Grid, cost: &C, cell: Cell) -> f32 {
+        cell.manhattan(&self.start) as f32 * cost.lower_bound(grid)
+    }
+
+    fn key<C: CostModel + ?Sized>(&self, grid: &Grid, cost: &C, cell: Cell) -> Key {
+        let best = self.g(cell).min(self.rhs(cell));
+        (best + self.heuristic(grid, cost, cell), best)
+    }
+
+    fn push(&mut self, cell: Cell, key: Key) {
+        self.queued.insert(cell, key);
+        self.open.push(Entry { key, cell });
+    }
+
+    fn neighbors(grid: &Grid, cell: Cell) -> impl Iterator<Item = Cell> + '_ {
+        (0..DIRECTIONS.len()).filter_map(move |dir| grid.neighbor(cell, dir))
+    }
+
+    fn update<C: CostModel + ?Sized>(&mut self, grid: &Grid, cost: &C, cell: Cell) {
+        if cell != self.goal {
+            let rhs = Self::neighbors(grid, cell)
+                .filter_map(|next| Some(cost.step_cost(grid, cell, next)? + self.g(next)))
+                .fold(f32::INFINITY, f32::min);
+            self.rhs.insert(cell, rhs);
+        }
+        self.queued.remove(&cell);
+        if self.g(cell) != self.rhs(cell) {
+            let key = self.key(grid, cost, cell);
+            self.push(cell, key);
+        }
+    }
+
+    fn compute<C: CostModel + ?Sized>(&mut self, grid: &Grid, cost: &C) -> bool {
+        let mut expansions = 0;
+        while let Some(Entry { key, cell }) = self.open.pop() {
+            if self.queued.get(&cell) != Some(&key) {
+                continue;
+            }
+            // Cells tied with the start are still expanded; rounding can hide an inconsistent
+            // cell on the route behind an equal key.
+            let start_key = self.key(grid, cost, self.start);
+            let consistent = self.rhs(self.start) == self.g(self.start);
+            if key.0 > start_key.0 + 1e-4 && consistent {
+                self.open.push(Entry { key, cell });
+                break;
+            }
+            expansions += 1;
+            if expansions > self.max_expansions {
+                self.open.push(Entry { key, cell });
+                return false;
+            }
+            let current = self.key(grid, cost, cell);
+            if key < current {
+                self.push(cell, current);
+                continue;
+            }
+            self.queued.remove(&cell);
+            if self.g(cell) > self.rhs(cell) {
+                self.g.insert(cell, self.rhs(cell));
+            } else {
+                self.g.insert(cell, f32::INFINITY);
+                self.update(grid, cost, cell);
+            }
+            let predecessors: Vec<Cell> = Self::neighbors(grid, cell).collect();
+            for previous in predecessors {
+                self.update(grid, cost, previous);
+            }
+        }
+        true
+    }
+
+    // Shortest route under the current grid, `None` if the goal cannot be reached.
+    pub fn plan<C: CostModel + ?Sized>(&mut self, grid: &Grid, cost: &C) -> Option<Vec<Cell>> {
+        if !self.compute(grid, cost) || !self.g(self.start).is_finite() {
+            return None;
+        }
+        let mut cells = vec![self.start];
+        let mut seen = HashSet::from([self.start]);
+        let mut cell = self.start;
+        let mut dir = None;
+        while cell != self.goal {
+            // Ties keep going straight so the route has no needless bends.
+            let (next, _) = (0..DIRECTIONS.len())
+                .filter_map(|d| {
+                    let next = grid.neighbor(cell, d)?;
+                    let total = cost.step_cost(grid, cell, next)? + self.g(next);
+                    let bend = if dir == Some(d) { 0.0 } else { 1e-4 };
+                    total.is_finite().then_some((next, total + bend))
+                })
+                .min_by(|a, b| a.1.total_cmp(&b.1))?;
+            if !seen.insert(next) {
+                return None;
+            }
+            dir = direction_between(cell, next);
+            cells.push(next);
+            cell = next;
+        }
+        Some(cells)
+    }
+
+    // Cost of the current best route, infinite when there is none.
+    pub fn cost(&self) -> f32 {
+        self.g(self.start)
+    }
+
+    // Tells the planner the cost of moving into or out of `changed` may differ now.
+    pub fn cells_changed<C: CostModel + ?Sized>(
+        &mut self,
+        grid: &Grid,
+        cost: &C,
+        changed: &[Cell],
+    ) {
+        let mut touched: HashSet<Cell> = HashSet::new();
+        for &cell in changed {
+            touched.insert(cell);
+            touched.extend(Self::neighbors(grid, cell));
+        }
+        for cell in touched {
+            self.update(grid, cost, cell);
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RepairStatus {
+    Unchanged,
+    // The route ran through a cell that is now blocked and was moved around it.
+    Repaired,
+    // Another route is now cheaper than the old one, e.g. through space that opened up.
+    Improved,
+    // No route is left between the endpoints.
+    Failed,
+}
+
+#[derive(Debug, Clone)]
+pub struct RouteRepair {
+    pub id: String,
+    pub status: RepairStatus,
+    pub cells: Option<Vec<Cell>>,
+    // Cost of `cells` on the updated grid, infinite when the route failed.
+    pub cost: f32,
+}
+
+impl RouteRepair {
+    pub fn is_affected(&self) -> bool {
+        self.status != RepairStatus::Unchanged
+    }
+}
+
+struct TrackedRoute {
+    id: String,
+    planner: DStarLite,
+    cells: Option<Vec<Cell>>,
+    cost: f32,
+}
+
+// Keeps a set of routes up to date as obstacles are added to or removed from the grid.
+pub struct Replanner<C: CostModel> {
+    cost: C,
+    routes: Vec<TrackedRoute>,
+    max_expansions: usize,
+}
+
+impl<C: CostModel> Replanner<C> {
+    pub fn new(cost: C, max_expansions: usize) -> Self {
+        Self {
+            cost,
+            routes: vec![],
+            max_expansions,
+        }
+    }
+
+    pub fn add_route(
+        &mut self,
+        grid: &Grid,
+        id: &str,
+        start: Cell,
+        goal: Cell,
+    ) -> Option<Vec<Cell>> {
+        let mut planner = DStarLite::new(start, goal, self.max_expansions);
+        let cells = planner.plan(grid, &self.cost);
+        self.routes.push(TrackedRoute {
+            id: id.to_string(),
+            cost: planner.cost(),
+            planner,
+            cells: cells.clone(),
+        });
+        cells
+    }
+
+    pub fn route(&self, id: &str) -> Option<&[Cell]> {
+        self.routes
+            .iter()
+            .find(|route| route.id == id)
+            .and_then(|route| route.cells.as_deref())
+    }
+
+    // Repairs every route after the cells in `changed` were blocked or freed, for example
+    // by `Grid::insert_obstacle` and `Grid::remove_obstacle`.
+    pub fn apply(&mut self, grid: &Grid, changed: &[Cell]) -> Vec<RouteRepair> {
+        let mut repairs = vec![];
+        for route in self.routes.iter_mut() {
+            route.planner.cells_changed(grid, &self.cost, changed);
+            // What keeping the old route would cost now, `None` if it is blocked.
+            let old_cost = route.cells.as_ref().and_then(|cells| {
+                cells
+                    .windows(2)
+                    .map(|pair| self.cost.step_cost(grid, pair[0], pair[1]))
+                    .sum::<Option<f32>>()
+            });
+            let cells = route.planner.plan(grid, &self.cost);
+            let cost = route.planner.cost();
+            let status = match (&cells, old_cost) {
+                (None, _) => RepairStatus::Failed,
+                (Some(_), None) => RepairStatus::Repaired,
+                (Some(_), Some(old_cost)) if cost < old_cost - 1e-4 => RepairStatus::Improved,
+                // The old route is as good as any, so it stays where it is.
+                (Some(_), Some(_)) => RepairStatus::Unchanged,
+            };
+            match status {
+                RepairStatus::Unchanged => route.cost = old_cost.unwrap_or(cost),
+                _ => {
+                    route.cells = cells;
+                    route.cost = cost;
+                }
+            }
+            repairs.push(RouteRepair {
+                id: route.id.clone(),
+                status,
+                cells: route.cells.clone(),
+                cost: route.cost,
+            });
+        }
+        repairs
+    }
+}
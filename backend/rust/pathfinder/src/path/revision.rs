@@ -0,0 +1,46 @@
+use crate::scene::{Scene, SceneDiff};
+
+use super::ClashPipe;
+
+// A route touched by differences between two versions of a scene.
+#[derive(Debug, Clone)]
+pub struct RouteImpact {
+    pub pipe: String,
+    // Indices into `SceneDiff::elements`.
+    pub elements: Vec<usize>,
+}
+
+// Routes whose pipe, at its full diameter, passes through space an added, removed or
+// changed element occupies in either version.
+pub fn affected_routes(
+    diff: &SceneDiff,
+    before: &Scene,
+    after: &Scene,
+    pipes: &[ClashPipe],
+) -> Vec<RouteImpact> {
+    let regions: Vec<_> = diff
+        .elements
+        .iter()
+        .map(|element| element.regions(before, after))
+        .collect();
+    pipes
+        .iter()
+        .filter_map(|pipe| {
+            let radius = pipe.diameter / 2.0;
+            let elements: Vec<usize> = regions
+                .iter()
+                .enumerate()
+                .filter(|(_, regions)| {
+                    regions
+                        .iter()
+                        .any(|region| pipe.path.crosses(&region.expand(radius)))
+                })
+                .map(|(i, _)| i)
+                .collect();
+            (!elements.is_empty()).then(|| RouteImpact {
+                pipe: pipe.id.clone(),
+                elements,
+            })
+        })
+        .collect()
+}
@@ -0,0 +1,540 @@
+use std::collections::{HashMap, HashSet};
+
+use super::{
+    attach_escapes, is_drop, opposite, Cell, CompiledConstraints, CostModel, Endpoint, Grid,
+    LayerField, LayerViolation, PathfindingPath, RuleField, RunConstraint, SearchParams,
+    SnappedEndpoint,
+};
+
+#[derive(Debug, Clone)]
+pub struct PipeRequest {
+    pub id: String,
+    pub start: Endpoint,
+    pub goal: Endpoint,
+    pub diameter: f32,
+    pub clearance: f32,
+    // Higher priority pipes are routed first and are the last to be ripped up.
+    pub priority: i32,
+    // Service system, used to look up its height band and the rules between systems.
+    pub system: Option<String>,
+}
+
+impl PipeRequest {
+    pub fn keep_out_radius(&self) -> f32 {
+        self.diameter / 2.0 + self.clearance
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct RouterConfig {
+    pub max_iterations: usize,
+    pub present_factor: f32,
+    pub present_growth: f32,
+    pub history_factor: f32,
+    // How far an endpoint inside an obstacle may be moved to reach free space.
+    pub snap_distance: f32,
+    pub search: SearchParams,
+}
+
+impl Default for RouterConfig {
+    fn default() -> Self {
+        Self {
+            max_iterations: 30,
+            present_factor: 0.5,
+            present_growth: 1.5,
+            history_factor: 1.0,
+            snap_distance: 1.0,
+            search: SearchParams::default(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct RoutedPipe {
+    pub id: String,
+    pub cells: Vec<Cell>,
+    pub path: PathfindingPath,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FailureReason {
+    EndpointOutside,
+    EndpointBlocked,
+    NoPath,
+    Congested,
+}
+
+#[derive(Debug)]
+pub struct FailedPipe {
+    pub id: String,
+    pub reason: FailureReason,
+    pub conflicts_with: Vec<String>,
+}
+
+#[derive(Debug, Default)]
+pub struct RoutingResult {
+    pub routed: Vec<RoutedPipe>,
+    pub failed: Vec<FailedPipe>,
+    pub iterations: usize,
+    pub layer_violations: Vec<LayerViolation>,
+}
+
+impl RoutingResult {
+    pub fn is_complete(&self) -> bool {
+        self.failed.is_empty()
+    }
+}
+
+struct Net {
+    start: Cell,
+    goal: Cell,
+    endpoints: [SnappedEndpoint; 2],
+    leave: RunConstraint,
+    arrive: RunConstraint,
+    radius: usize,
+    system: Option<String>,
+    cells: Option<Vec<Cell>>,
+}
+
+struct Conflict {
+    a: usize,
+    b: usize,
+    cells: Vec<Cell>,
+}
+
+struct PipeCost<'r> {
+    clearance: &'r [u32],
+    radius: usize,
+    endpoints: [Cell; 2],
+    history: &'r [f32],
+    present: &'r [u32],
+    present_factor: f32,
+    // Cells inside the height band of the pipe's system.
+    band: Option<&'r [bool]>,
+    violation_cost: f32,
+    // Cells the system's rules keep the pipe out of, such as the space above a panel.
+    forbidden: Option<&'r [bool]>,
+    constraints: Option<&'r CompiledConstraints>,
+}
+
+impl CostModel for PipeCost<'_> {
+    fn step_cost(&self, grid: &Grid, _from: Cell, to: Cell) -> Option<f32> {
+        if !grid.is_free(to) {
+            return None;
+        }
+        let i = grid.index(to);
+        let blocked = self.forbidden.is_some_and(|forbidden| forbidden[i])
+            || self
+                .constraints
+                .is_some_and(|constraints| constraints.is_blocked(grid, to));
+        if blocked && !self.endpoints.iter().any(|&endpoint| is_drop(endpoint, to)) {
+            return None;
+        }
+        let near_endpoint = self
+            .endpoints
+            .iter()
+            .any(|endpoint| endpoint.chebyshev(&to) <= self.radius);
+        if !near_endpoint && (self.clearance[i] as usize) < self.radius {
+            return None;
+        }
+        let present = self.present[i] as f32;
+        if present > 0.0 && self.present_factor.is_infinite() {
+            return None;
+        }
+        let outside_band = self.band.is_some_and(|band| !band[i])
+            && !self.endpoints.iter().any(|&endpoint| is_drop(endpoint, to));
+        let layer = if outside_band {
+            self.violation_cost
+        } else {
+            0.0
+        };
+        let penalty = self
+            .constraints
+            .map_or(0.0, |constraints| constraints.penalty(grid, to));
+        Some(
+            (grid.cell_size() + self.history[i] + layer + penalty)
+                * (1.0 + self.present_factor * present),
+        )
+    }
+}
+
+// Batch router using negotiated congestion: pipes may share space early on, but the cost
+// of shared cells grows every iteration until the pipes spread out or the budget runs out.
+pub struct Router<'a> {
+    grid: &'a Grid,
+    clearance: Vec<u32>,
+    config: RouterConfig,
+    layers: Option<&'a LayerField>,
+    rules: Option<&'a RuleField>,
+    constraints: Option<&'a CompiledConstraints>,
+}
+
+impl<'a> Router<'a> {
+    pub fn new(grid: &'a Grid, config: RouterConfig) -> Self {
+        Self {
+            grid,
+            clearance: grid.clearance(),
+            config,
+            layers: None,
+            rules: None,
+            constraints: None,
+        }
+    }
+
+    // Keeps every pipe with a system inside that system's height band where it can.
+    pub fn with_layers(mut self, layers: &'a LayerField) -> Self {
+        self.layers = Some(layers);
+        self
+    }
+
+    // Enforces the clearance and placement rules between systems.
+    pub fn with_rules(mut self, rules: &'a RuleField) -> Self {
+        self.rules = Some(rules);
+        self
+    }
+
+    // Applies project constraints compiled from a constraint file to every pipe.
+    pub fn with_constraints(mut self, constraints: &'a CompiledConstraints) -> Self {
+        self.constraints = Some(constraints);
+        self
+    }
+
+    pub fn route(&self, requests: &[PipeRequest]) -> RoutingResult {
+        let mut result = RoutingResult::default();
+        let mut order: Vec<usize> = (0..requests.len()).collect();
+        order.sort_by_key(|&i| std::cmp::Reverse(requests[i].priority));
+
+        let mut nets: Vec<Option<Net>> = requests
+            .iter()
+            .map(|request| match self.resolve(request) {
+                Ok(net) => Some(net),
+                Err(reason) => {
+                    result.failed.push(FailedPipe {
+                        id: request.id.clone(),
+                        reason,
+                        conflicts_with: vec![],
+                    });
+                    None
+                }
+            })
+            .collect();
+
+        let mut history = vec![0.0; self.grid.len()];
+        let mut present_factor = self.config.present_factor;
+        let mut conflicts = vec![];
+        for iteration in 0..self.config.max_iterations {
+            result.iterations = iteration + 1;
+            for &p in order.iter() {
+                let Some(net) = nets[p].as_mut() else {
+                    continue;
+                };
+                net.cells = None;
+                let present = self.present(&nets, p);
+                let net = nets[p].as_mut().unwrap();
+                net.cells = self.search(net, &history, &present, present_factor);
+                if net.cells.is_none() {
+                    nets[p] = None;
+                    result.failed.push(FailedPipe {
+                        id: requests[p].id.clone(),
+                        reason: FailureReason::NoPath,
+                        conflicts_with: vec![],
+                    });
+                }
+            }
+            conflicts = self.conflicts(&nets);
+            if conflicts.is_empty() {
+                break;
+            }
+            for conflict in conflicts.iter() {
+                for &cell in conflict.cells.iter() {
+                    history[self.grid.index(cell)] += self.config.history_factor;
+                }
+            }
+            present_factor *= self.config.present_growth;
+        }
+
+        // The lowest priority pipe among `conflicts`, with the ids of the pipes it conflicts
+        // with.
+        let victim = |conflicts: &[Conflict]| {
+            let rank = |i: usize| order.iter().position(|&p| p == i).unwrap();
+            let victim = conflicts
+                .iter()
+                .flat_map(|conflict| [conflict.a, conflict.b])
+                .max_by_key(|&i| rank(i))
+                .unwrap();
+            let conflicts_with: Vec<String> = conflicts
+                .iter()
+                .filter_map(|conflict| match (conflict.a, conflict.b) {
+                    (a, b) if a == victim => Some(requests[b].id.clone()),
+                    (a, b) if b == victim => Some(requests[a].id.clone()),
+                    _ => None,
+                })
+                .collect();
+            (victim, conflicts_with)
+        };
+
+        // Rip up the lowest priority pipe of every remaining conflict, then try to reroute
+        // the ripped pipes around everything that is left in place.
+        let mut ripped = vec![];
+        while !conflicts.is_empty() {
+            let (p, conflicts_with) = victim(&conflicts);
+            nets[p].as_mut().unwrap().cells = None;
+            ripped.push((p, conflicts_with));
+            conflicts = self.conflicts(&nets);
+        }
+        for (p, conflicts_with) in ripped {
+            let present = self.present(&nets, p);
+            let net = nets[p].as_mut().unwrap();
+            net.cells = self.search(net, &history, &present, f32::INFINITY);
+            if net.cells.is_none() {
+                nets[p] = None;
+                result.failed.push(FailedPipe {
+                    id: requests[p].id.clone(),
+                    reason: FailureReason::Congested,
+                    conflicts_with,
+                });
+            }
+        }
+        // Reroutes only avoid what they could see; whatever still conflicts is given up.
+        conflicts = self.conflicts(&nets);
+        while !conflicts.is_empty() {
+            let (p, conflicts_with) = victim(&conflicts);
+            nets[p] = None;
+            result.failed.push(FailedPipe {
+                id: requests[p].id.clone(),
+                reason: FailureReason::Congested,
+                conflicts_with,
+            });
+            conflicts = self.conflicts(&nets);
+        }
+
+        for (request, net) in requests.iter().zip(nets) {
+            let Some(Net {
+                endpoints: [start, goal],
+                cells: Some(cells),
+                ..
+            }) = net
+            else {
+                continue;
+            };
+            if let (Some(layers), Some(system)) = (self.layers, request.system.as_deref()) {
+                result.layer_violations.extend(layers.violations(
+                    self.grid,
+                    &request.id,
+                    system,
+                    &cells,
+                ));
+            }
+            let path = attach_escapes(&start, self.grid.cells_to_path(&cells), &goal);
+            result.routed.push(RoutedPipe {
+                id: request.id.clone(),
+                cells,
+                path,
+            });
+        }
+        result
+    }
+
+    fn resolve(&self, request: &PipeRequest) -> Result<Net, FailureReason> {
+        let snap = |endpoint: &Endpoint| {
+            self.grid
+                .cell_at(endpoint.position)
+                .ok_or(FailureReason::EndpointOutside)?;
+            self.grid
+                .snap(endpoint, self.config.snap_distance)
+                .ok_or(FailureReason::EndpointBlocked)
+        };
+        let start = snap(&request.start)?;
+        let goal = snap(&request.goal)?;
+        let cell_size = self.grid.cell_size();
+        let leave = start.run_constraint(&request.start, cell_size);
+        let mut arrive = goal.run_constraint(&request.goal, cell_size);
+        arrive.dir = arrive.dir.map(opposite);
+        Ok(Net {
+            start: start.cell,
+            goal: goal.cell,
+            leave,
+            arrive,
+            endpoints: [start, goal],
+            radius: self.radius_in_cells(request.keep_out_radius()),
+            system: request.system.clone(),
+            cells: None,
+        })
+    }
+
+    fn radius_in_cells(&self, radius: f32) -> usize {
+        (radius / self.grid.cell_size() - 0.5).ceil().max(0.0) as usize
+    }
+
+    fn search(
+        &self,
+        net: &Net,
+        history: &[f32],
+        present: &[u32],
+        present_factor: f32,
+    ) -> Option<Vec<Cell>> {
+        let cost = PipeCost {
+            clearance: &self.clearance,
+            radius: net.radius,
+            endpoints: [net.start, net.goal],
+            history,
+            present,
+            present_factor,
+            band: self
+                .layers
+                .zip(net.system.as_deref())
+                .and_then(|(layers, system)| layers.mask(system)),
+            violation_cost: self.layers.map_or(0.0, |layers| layers.violation_cost),
+            forbidden: self
+                .rules
+                .zip(net.system.as_deref())
+                .and_then(|(rules, system)| rules.forbidden(system)),
+            constraints: self.constraints,
+        };
+        let params = self.constraints.map_or(self.config.search, |constraints| {
+            constraints.params(&self.config.search)
+        });
+        self.grid.search_constrained(
+            &[net.start],
+            &[net.goal],
+            net.leave,
+            net.arrive,
+            &cost,
+            &params,
+        )
+    }
+
+    // Centre-to-centre distance, in cells, that pipes `a` and `b` must keep.
+    fn separation(&self, a: &Net, b: &Net) -> usize {
+        let extra = match (self.rules, a.system.as_deref(), b.system.as_deref()) {
+            (Some(rules), Some(x), Some(y)) => rules.separation_cells(self.grid, x, y),
+            _ => 0,
+        };
+        a.radius + b.radius + extra
+    }
+
+    // Distance in plan, in cells, by which `upper` must stay off the top of `lower`.
+    fn overhang(&self, upper: &Net, lower: &Net) -> Option<usize> {
+        let rules = self.rules?;
+        let margin = rules.above_margin_cells(
+            self.grid,
+            upper.system.as_deref()?,
+            lower.system.as_deref()?,
+        )?;
+        Some(margin + upper.radius + lower.radius)
+    }
+
+    // Cells of `upper` lying above `lower` within `reach` columns in plan.
+    fn above(&self, upper: &[Cell], lower: &[Cell], reach: usize) -> Vec<Cell> {
+        let mut floor: HashMap<(usize, usize), usize> = HashMap::new();
+        for cell in lower.iter() {
+            let z = floor.entry((cell.x, cell.y)).or_insert(cell.z);
+            *z = (*z).min(cell.z);
+        }
+        upper
+            .iter()
+            .copied()
+            .filter(|cell| {
+                (cell.x.saturating_sub(reach)..=cell.x + reach).any(|x| {
+                    (cell.y.saturating_sub(reach)..=cell.y + reach)
+                        .any(|y| floor.get(&(x, y)).is_some_and(|&z| z < cell.z))
+                })
+            })
+            .collect()
+    }
+
+    // Number of other routed pipes whose keep-out zone, or the space above or below them that
+    // pipe `p` must keep out of, a centreline of `p` would hit in each cell.
+    fn present(&self, nets: &[Option<Net>], p: usize) -> Vec<u32> {
+        let own = nets[p].as_ref();
+        let extent = self.grid.extent();
+        let mut present = vec![0; self.grid.len()];
+        let mut stamp = vec![usize::MAX; self.grid.len()];
+        for (q, net) in nets.iter().enumerate() {
+            let Some((net, cells)) = net
+                .as_ref()
+                .and_then(|net| net.cells.as_ref().map(|cells| (net, cells)))
+            else {
+                continue;
+            };
+            if q == p {
+                continue;
+            }
+            let distance = own.map_or(net.radius, |own| self.separation(own, net));
+            let mut mark = |near: Cell| {
+                let i = self.grid.index(near);
+                if stamp[i] != q {
+                    stamp[i] = q;
+                    present[i] += 1;
+                }
+            };
+            for &cell in cells.iter() {
+                for near in self.grid.cells_within(cell, distance) {
+                    mark(near);
+                }
+            }
+            // The columns above a pipe this one may not pass over.
+            if let Some(reach) = own.and_then(|own| self.overhang(own, net)) {
+                for &cell in cells.iter() {
+                    for x in cell.x.saturating_sub(reach)..(cell.x + reach + 1).min(extent.x) {
+                        for y in cell.y.saturating_sub(reach)..(cell.y + reach + 1).min(extent.y) {
+                            for z in cell.z + 1..extent.z {
+                                mark(Cell::new(x, y, z));
+                            }
+                        }
+                    }
+                }
+            }
+            // The columns below a pipe that may not pass over this one.
+            if let Some(reach) = own.and_then(|own| self.overhang(net, own)) {
+                for &cell in cells.iter() {
+                    for x in cell.x.saturating_sub(reach)..(cell.x + reach + 1).min(extent.x) {
+                        for y in cell.y.saturating_sub(reach)..(cell.y + reach + 1).min(extent.y) {
+                            for z in 0..cell.z {
+                                mark(Cell::new(x, y, z));
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        present
+    }
+
+    fn conflicts(&self, nets: &[Option<Net>]) -> Vec<Conflict> {
+        let routed: Vec<(usize, &Net, &Vec<Cell>)> = nets
+            .iter()
+            .enumerate()
+            .filter_map(|(i, net)| {
+                let net = net.as_ref()?;
+                Some((i, net, net.cells.as_ref()?))
+            })
+            .collect();
+        let mut conflicts = vec![];
+        for (n, &(a, net_a, cells_a)) in routed.iter().enumerate() {
+            for &(b, net_b, cells_b) in routed.iter().skip(n + 1) {
+                let occupied: HashSet<Cell> = cells_b.iter().copied().collect();
+                let distance = self.separation(net_a, net_b);
+                let mut cells: Vec<Cell> = cells_a
+                    .iter()
+                    .copied()
+                    .filter(|&cell| {
+                        self.grid
+                            .cells_within(cell, distance)
+                            .any(|near| occupied.contains(&near))
+                    })
+                    .collect();
+                if let Some(reach) = self.overhang(net_a, net_b) {
+                    cells.extend(self.above(cells_a, cells_b, reach));
+                }
+                if let Some(reach) = self.overhang(net_b, net_a) {
+                    cells.extend(self.above(cells_b, cells_a, reach));
+                }
+                if !cells.is_empty() {
+                    conflicts.push(Conflict { a, b, cells });
+                }
+            }
+        }
+        conflicts
+    }
+}
@@ -0,0 +1,302 @@
+use std::collections::HashMap;
+
+use crate::{
+    math::vector::Vector3,
+    scene::{Bounds, Scene},
+};
+
+use super::{segment_segment, ClashItem, ClashPipe, Grid};
+
+#[derive(Debug, Clone)]
+pub enum RuleTarget {
+    System(String),
+    // IFC class of scene elements.
+    Class(String),
+}
+
+#[derive(Debug, Clone)]
+pub enum RuleKind {
+    // Minimum gap between the surfaces of pipes of two systems.
+    Clearance {
+        a: String,
+        b: String,
+        distance: f32,
+    },
+    // Pipes of `system` must not pass over `target`, nor within `margin` of it in plan.
+    NotAbove {
+        system: String,
+        target: RuleTarget,
+        margin: f32,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct SystemRule {
+    pub id: String,
+    pub kind: RuleKind,
+}
+
+#[derive(Debug, Clone)]
+pub struct RuleMatrix {
+    pub rules: Vec<SystemRule>,
+}
+
+impl Default for RuleMatrix {
+    fn default() -> Self {
+        let rule = |id: &str, kind| SystemRule {
+            id: id.to_string(),
+            kind,
+        };
+        Self {
+            rules: vec![
+                rule(
+                    "SEP-GAS-ELEC",
+                    RuleKind::Clearance {
+                        a: "Gas".to_string(),
+                        b: "Electrical".to_string(),
+                        distance: 0.15,
+                    },
+                ),
+                rule(
+                    "ABV-HW-PANEL",
+                    RuleKind::NotAbove {
+                        system: "HotWater".to_string(),
+                        target: RuleTarget::Class("IfcElectricDistributionBoard".to_string()),
+                        margin: 0.3,
+                    },
+                ),
+                rule(
+                    "ABV-HW-ELEC",
+                    RuleKind::NotAbove {
+                        system: "HotWater".to_string(),
+                        target: RuleTarget::System("Electrical".to_string()),
+                        margin: 0.1,
+                    },
+                ),
+            ],
+        }
+    }
+}
+
+impl RuleMatrix {
+    // Strictest clearance rule between two systems, in either order.
+    pub fn clearance(&self, a: &str, b: &str) -> Option<(&SystemRule, f32)> {
+        self.rules
+            .iter()
+            .filter_map(|rule| match &rule.kind {
+                RuleKind::Clearance {
+                    a: x,
+                    b: y,
+                    distance,
+                } if (x == a && y == b) || (x == b && y == a) => Some((rule, *distance)),
+                _ => None,
+            })
+            .max_by(|x, y| x.1.total_cmp(&y.1))
+    }
+
+    // Widest margin by which `upper` must stay off the top of pipes of `lower`.
+    pub fn not_above_system(&self, upper: &str, lower: &str) -> Option<(&SystemRule, f32)> {
+        self.rules
+            .iter()
+            .filter_map(|rule| match &rule.kind {
+                RuleKind::NotAbove {
+                    system,
+                    target: RuleTarget::System(target),
+                    margin,
+                } if system == upper && target == lower => Some((rule, *margin)),
+                _ => None,
+            })
+            .max_by(|x, y| x.1.total_cmp(&y.1))
+    }
+
+    fn not_above_class<'a>(
+        &'a self,
+        upper: &'a str,
+    ) -> impl Iterator<Item = (&'a SystemRule, &'a str, f32)> + 'a {
+        self.rules.iter().filter_map(move |rule| match &rule.kind {
+            RuleKind::NotAbove {
+                system,
+                target: RuleTarget::Class(class),
+                margin,
+            } if system == upper => Some((rule, class.as_str(), *margin)),
+            _ => None,
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RuleViolation {
+    pub rule: String,
+    // Index into the checked pipes.
+    pub pipe: usize,
+    pub other: ClashItem,
+    pub segment: usize,
+    pub position: Vector3,
+    pub measured: f32,
+    pub required: f32,
+}
+
+// Space above an element, widened by the margin in plan, up to `ceiling`.
+fn shadow(bounds: &Bounds, margin: f32, ceiling: f32) -> Bounds {
+    Bounds::new(
+        Vector3::new(bounds.min.x - margin, bounds.min.y - margin, bounds.max.z),
+        Vector3::new(bounds.max.x + margin, bounds.max.y + margin, ceiling),
+    )
+}
+
+fn flat(v: Vector3) -> Vector3 {
+    Vector3::new(v.x, v.y, 0.0)
+}
+
+fn segments(pipe: &ClashPipe) -> impl Iterator<Item = (usize, Vector3, Vector3)> + '_ {
+    pipe.path
+        .points
+        .windows(2)
+        .enumerate()
+        .map(|(i, pair)| (i, pair[0], pair[1]))
+}
+
+// Checks existing routes against every rule, reporting the worst spot per rule and pair.
+pub fn check_rules(scene: &Scene, pipes: &[ClashPipe], matrix: &RuleMatrix) -> Vec<RuleViolation> {
+    let mut violations: Vec<RuleViolation> = vec![];
+    let mut report = |violation: RuleViolation| match violations.iter_mut().find(|v| {
+        v.rule == violation.rule && v.pipe == violation.pipe && v.other == violation.other
+    }) {
+        Some(existing) if existing.measured > violation.measured => *existing = violation,
+        Some(_) => {}
+        None => violations.push(violation),
+    };
+
+    for (p, pipe) in pipes.iter().enumerate() {
+        for (q, other) in pipes.iter().enumerate() {
+            if p == q {
+                continue;
+            }
+            let radii = (pipe.diameter + other.diameter) / 2.0;
+            let clearance = matrix
+                .clearance(&pipe.class, &other.class)
+                .filter(|_| p < q);
+            let above = matrix.not_above_system(&pipe.class, &other.class);
+            if clearance.is_none() && above.is_none() {
+                continue;
+            }
+            for (segment, a, b) in segments(pipe) {
+                for (_, c, d) in segments(other) {
+                    if let Some((rule, required)) = clearance {
+                        let (distance, s, _) = segment_segment(a, b, c, d);
+                        if distance - radii < required {
+                            report(RuleViolation {
+                                rule: rule.id.clone(),
+                                pipe: p,
+                                other: ClashItem::Pipe(q),
+                                segment,
+                                position: a + s * (b - a),
+                                measured: distance - radii,
+                                required,
+                            });
+                        }
+                    }
+                    if let Some((rule, margin)) = above {
+                        let (distance, s, t) = segment_segment(flat(a), flat(b), flat(c), flat(d));
+                        let upper = a + s * (b - a);
+                        let lower = c + t * (d - c);
+                        if distance - radii < margin && upper.z > lower.z {
+                            report(RuleViolation {
+                                rule: rule.id.clone(),
+                                pipe: p,
+                                other: ClashItem::Pipe(q),
+                                segment,
+                                position: upper,
+                                measured: distance - radii,
+                                required: margin,
+                            });
+                        }
+                    }
+                }
+            }
+        }
+
+        for (rule, class, margin) in matrix.not_above_class(&pipe.class) {
+            for (element, bbox) in scene.obstacles.iter().enumerate() {
+                if !bbox.is_class(class) {
+                    continue;
+                }
+                let region = shadow(&bbox.bounds, margin + pipe.diameter / 2.0, f32::MAX);
+                for (segment, a, b) in segments(pipe) {
+                    if let Some((t0, t1)) = region.intersect_segment(a, b) {
+                        let position = a + ((t0 + t1) / 2.0) * (b - a);
+                        let footprint = Bounds::new(
+                            flat(bbox.bounds.min),
+                            Vector3::new(bbox.bounds.max.x, bbox.bounds.max.y, 0.0),
+                        );
+                        let (measured, _) = footprint.segment_distance(flat(a), flat(b));
+                        report(RuleViolation {
+                            rule: rule.id.clone(),
+                            pipe: p,
+                            other: ClashItem::Element(element),
+                            segment,
+                            position,
+                            measured,
+                            required: margin,
+                        });
+                    }
+                }
+            }
+        }
+    }
+    violations
+}
+
+// Rules prepared for routing on a grid: cells each system may not enter, and the extra
+// separation required between systems. Forbidden regions are widened by `radius`, the
+// largest pipe radius to be routed, so centrelines stay where `check_rules` accepts them.
+pub struct RuleField {
+    pub matrix: RuleMatrix,
+    forbidden: HashMap<String, Vec<bool>>,
+}
+
+impl RuleField {
+    pub fn build(grid: &Grid, scene: &Scene, matrix: &RuleMatrix, radius: f32) -> Self {
+        let mut forbidden: HashMap<String, Vec<bool>> = HashMap::new();
+        let ceiling = grid.bounds().max.z;
+        for rule in matrix.rules.iter() {
+            let RuleKind::NotAbove {
+                system,
+                target: RuleTarget::Class(class),
+                margin,
+            } = &rule.kind
+            else {
+                continue;
+            };
+            let mask = forbidden
+                .entry(system.clone())
+                .or_insert_with(|| vec![false; grid.len()]);
+            for bbox in scene.obstacles.iter().filter(|bbox| bbox.is_class(class)) {
+                for cell in grid.cells_in(&shadow(&bbox.bounds, margin + radius, ceiling)) {
+                    mask[grid.index(cell)] = true;
+                }
+            }
+        }
+        Self {
+            matrix: matrix.clone(),
+            forbidden,
+        }
+    }
+
+    pub fn forbidden(&self, system: &str) -> Option<&[bool]> {
+        self.forbidden.get(system).map(|mask| mask.as_slice())
+    }
+
+    // Extra gap between two systems, in whole cells.
+    pub fn separation_cells(&self, grid: &Grid, a: &str, b: &str) -> usize {
+        self.matrix.clearance(a, b).map_or(0, |(_, distance)| {
+            (distance / grid.cell_size()).ceil() as usize
+        })
+    }
+
+    pub fn above_margin_cells(&self, grid: &Grid, upper: &str, lower: &str) -> Option<usize> {
+        self.matrix
+            .not_above_system(upper, lower)
+            .map(|(_, margin)| (margin / grid.cell_size()).ceil() as usize)
+    }
+}
@@ -0,0 +1,340 @@
+use std::{
+    cmp::Ordering,
+    collections::{BinaryHeap, HashMap, HashSet},
+};
+
+use super::{direction_between, opposite, Cell, Grid, DIRECTIONS};
+
+pub trait CostModel {
+    // Cost of moving between two adjacent cells, `None` if the move is not allowed.
+    fn step_cost(&self, grid: &Grid, from: Cell, to: Cell) -> Option<f32>;
+
+    // Lower bound on the cost of a single step, used to keep the heuristic admissible.
+    fn lower_bound(&self, grid: &Grid) -> f32 {
+        grid.cell_size()
+    }
+}
+
+pub struct Uniform {}
+
+impl CostModel for Uniform {
+    fn step_cost(&self, grid: &Grid, _from: Cell, to: Cell) -> Option<f32> {
+        grid.is_free(to).then_some(grid.cell_size())
+    }
+}
+
+impl<C: CostModel + ?Sized> CostModel for &C {
+    fn step_cost(&self, grid: &Grid, from: Cell, to: Cell) -> Option<f32> {
+        (**self).step_cost(grid, from, to)
+    }
+
+    fn lower_bound(&self, grid: &Grid) -> f32 {
+        (**self).lower_bound(grid)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct SearchParams {
+    pub bend_cost: f32,
+    pub max_expansions: usize,
+    // Routes with more bends than this are not considered.
+    pub max_bends: Option<usize>,
+}
+
+impl Default for SearchParams {
+    fn default() -> Self {
+        Self {
+            bend_cost: 1.0,
+            max_expansions: 2_000_000,
+            max_bends: None,
+        }
+    }
+}
+
+// Requirement on the straight run at one end of a route: the run must follow `dir` when
+// given (for the goal, the direction of arrival) and span at least `min_run` steps.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RunConstraint {
+    pub dir: Option<usize>,
+    pub min_run: usize,
+}
+
+impl RunConstraint {
+    fn satisfied(&self, dir: Option<usize>, run: usize) -> bool {
+        self.dir.is_none_or(|required| dir == Some(required)) && run >= self.min_run
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct State {
+    cell: Cell,
+    dir: Option<usize>,
+    // Steps taken along `dir`, capped at the longest run any constraint asks for.
+    run: usize,
+    bent: bool,
+    // Bends so far, only counted when the search limits them.
+    bends: usize,
+}
+
+struct Open {
+    f: f32,
+    g: f32,
+    state: State,
+}
+
+impl PartialEq for Open {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Open {}
+
+impl PartialOrd for Open {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Open {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .f
+            .total_cmp(&self.f)
+            .then_with(|| self.g.total_cmp(&other.g))
+    }
+}
+
+struct Goals {
+    cells: HashSet<Cell>,
+    min: Cell,
+    max: Cell,
+}
+
+impl Goals {
+    fn new(goals: &[Cell]) -> Self {
+        let cells: HashSet<Cell> = goals.iter().copied().collect();
+        let min = Cell::new(
+            goals.iter().map(|c| c.x).min().unwrap_or(0),
+            goals.iter().map(|c| c.y).min().unwrap_or(0),
+            goals.iter().map(|c| c.z).min().unwrap_or(0),
+        );
+        let max = Cell::new(
+            goals.iter().map(|c| c.x).max().unwrap_or(0),
+            goals.iter().map(|c| c.y).max().unwrap_or(0),
+            goals.iter().map(|c| c.z).max().unwrap_or(0),
+        );
+        Self { cells, min, max }
+    }
+
+    // Manhattan distance to the bounding box of all goals.
+    fn distance(&self, cell: Cell) -> usize {
+        let axis = |v: usize, min: usize, max: usize| {
+            if v < min {
+                min - v
+            } else {
+                v.saturating_sub(max)
+            }
+        };
+        axis(cell.x, self.min.x, self.max.x)
+            + axis(cell.y, self.min.y, self.max.y)
+            + axis(cell.z, self.min.z, self.max.z)
+    }
+}
+
+pub fn count_bends(cells: &[Cell]) -> usize {
+    cells
+        .windows(3)
+        .filter(|w| direction_between(w[0], w[1]) != direction_between(w[1], w[2]))
+        .count()
+}
+
+impl Grid {
+    // Bend-aware A* over the six axis-aligned moves from any of `starts` to any of `goals`.
+    pub fn search<C: CostModel + ?Sized>(
+        &self,
+        starts: &[Cell],
+        goals: &[Cell],
+        cost: &C,
+        params: &SearchParams,
+    ) -> Option<Vec<Cell>> {
+        let free = RunConstraint::default();
+        self.search_constrained(starts, goals, free, free, cost, params)
+    }
+
+    pub fn search_constrained<C: CostModel + ?Sized>(
+        &self,
+        starts: &[Cell],
+        goals: &[Cell],
+        leave: RunConstraint,
+        arrive: RunConstraint,
+        cost: &C,
+        params: &SearchParams,
+    ) -> Option<Vec<Cell>> {
+        if starts.is_empty() || goals.is_empty() {
+            return None;
+        }
+        let goals = Goals::new(goals);
+        let step = cost.lower_bound(self);
+        let heuristic = |cell: Cell| goals.distance(cell) as f32 * step;
+
+        let mut open = BinaryHeap::new();
+        let mut g_score: HashMap<State, f32> = HashMap::new();
+        let mut came_from: HashMap<State, State> = HashMap::new();
+        let max_run = leave.min_run.max(arrive.min_run);
+        let track_leave = leave != RunConstraint::default();
+        for &cell in starts {
+            let state = State {
+                cell,
+                dir: None,
+                run: 0,
+                bent: false,
+                bends: 0,
+            };
+            g_score.insert(state, 0.0);
+            open.push(Open {
+                f: heuristic(cell),
+                g: 0.0,
+                state,
+            });
+        }
+
+        let mut expansions = 0;
+        while let Some(Open { g, state, .. }) = open.pop() {
+            if g > *g_score.get(&state).unwrap_or(&f32::INFINITY) {
+                continue;
+            }
+            let leaving = !state.bent;
+            if goals.cells.contains(&state.cell)
+                && arrive.satisfied(state.dir, state.run)
+                && (!leaving || leave.satisfied(state.dir, state.run))
+            {
+                return Some(reconstruct(&came_from, state));
+            }
+            expansions += 1;
+            if expansions > params.max_expansions {
+                return None;
+            }
+            for dir in 0..DIRECTIONS.len() {
+                // Turning back onto the cell just left would fold the pipe onto itself.
+                if state.dir.is_some_and(|prev| dir == opposite(prev)) {
+                    continue;
+                }
+                let turns = state.dir.is_some_and(|prev| prev != dir);
+                if (state.dir.is_none() && leave.dir.is_some_and(|required| required != dir))
+                    || (turns && leaving && !leave.satisfied(state.dir, state.run))
+                {
+                    continue;
+                }
+                let bends = match params.max_bends {
+                    Some(max) if turns && state.bends == max => continue,
+                    Some(_) => state.bends + usize::from(turns),
+                    None => 0,
+                };
+                let Some(next) = self.neighbor(state.cell, dir) else {
+                    continue;
+                };
+                let Some(step_cost) = cost.step_cost(self, state.cell, next) else {
+                    continue;
+                };
+                let bend = if turns { params.bend_cost } else { 0.0 };
+                let next_state = State {
+                    cell: next,
+                    dir: Some(dir),
+                    run: if turns {
+                        1.min(max_run)
+                    } else {
+                        (state.run + 1).min(max_run)
+                    },
+                    bent: track_leave && (state.bent || turns),
+                    bends,
+                };
+                let tentative = g + step_cost + bend;
+                if tentative < *g_score.get(&next_state).unwrap_or(&f32::INFINITY) {
+                    g_score.insert(next_state, tentative);
+                    came_from.insert(next_state, state);
+                    open.push(Open {
+                        f: tentative + heuristic(next),
+                        g: tentative,
+                        state: next_state,
+                    });
+                }
+            }
+        }
+        None
+    }
+}
+
+fn reconstruct(came_from: &HashMap<State, State>, mut current: State) -> Vec<Cell> {
+    let mut cells = vec![current.cell];
+    while let Some(&previous) = came_from.get(&current) {
+        cells.push(previous.cell);
+        current = previous;
+    }
+    cells.reverse();
+    cells
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+    use crate::{
+        math::vector::Vector3,
+        path::DisallowInterior,
+        scene::{BBox, Bounds, Scene},
+    };
+
+    // Empty 10 x 3 x 1 grid of unit cells.
+    fn corridor() -> Grid {
+        let bounds = Bounds::new(Vector3::new(0.0, 0.0, 0.0), Vector3::new(9.0, 2.0, 0.0));
+        let space = BBox {
+            bounds,
+            class: Some("IfcSpace".to_string()),
+            global_id: None,
+            name: None,
+            penetrable: None,
+        };
+        let scene = Scene {
+            room: space.clone(),
+            obstacles: vec![space],
+            bounds,
+        };
+        Grid::build::<DisallowInterior>(&scene, 1.0)
+    }
+
+    #[test]
+    fn leave_constraint_away_from_goal_does_not_reverse() {
+        let grid = corridor();
+        let (start, goal) = (Cell::new(5, 1, 0), Cell::new(2, 1, 0));
+        let leave = RunConstraint {
+            dir: Some(0),
+            min_run: 3,
+        };
+        let cells = grid
+            .search_constrained(
+                &[start],
+                &[goal],
+                leave,
+                RunConstraint::default(),
+                &Uniform {},
+                &SearchParams::default(),
+            )
+            .unwrap();
+        assert_eq!(
+            cells[..4].to_vec(),
+            (5..9).map(|x| Cell::new(x, 1, 0)).collect::<Vec<_>>()
+        );
+        assert_eq!(*cells.last().unwrap(), goal);
+        let unique: HashSet<Cell> = cells.iter().copied().collect();
+        assert_eq!(unique.len(), cells.len());
+        for pair in cells.windows(3) {
+            let (a, b) = (
+                direction_between(pair[0], pair[1]),
+                direction_between(pair[1], pair[2]),
+            );
+            assert_ne!(a.map(opposite), b);
+        }
+    }
+}
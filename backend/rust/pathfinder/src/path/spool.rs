@@ -0,0 +1,250 @@
+use serde::Serialize;
+
+use crate::{math::vector::Vector3, utility::GenError};
+
+use super::{PathfindingPath, PipeSpec};
+
+#[derive(Debug, Clone, Copy)]
+pub struct SpoolConfig {
+    // Largest box a spool may occupy for transport, in any axis-aligned orientation.
+    pub envelope: Vector3,
+    pub stock_length: f32,
+    // Material lost to every cut.
+    pub kerf: f32,
+}
+
+impl Default for SpoolConfig {
+    fn default() -> Self {
+        Self {
+            envelope: Vector3::new(6.0, 2.4, 2.4),
+            stock_length: 6.0,
+            kerf: 0.003,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Spool {
+    // Index of the run the spool was cut from.
+    pub run: usize,
+    pub material: String,
+    pub diameter: f32,
+    // Minimum corner of the spool box; `points` are relative to it.
+    pub origin: [f32; 3],
+    pub points: Vec<[f32; 3]>,
+    pub extent: [f32; 3],
+    pub elbows: usize,
+    // Straight pipe lengths between fittings, no longer than a stock length.
+    pub pieces: Vec<f32>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Cut {
+    pub spool: usize,
+    pub piece: usize,
+    pub length: f32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StockBar {
+    pub material: String,
+    pub diameter: f32,
+    pub cuts: Vec<Cut>,
+    pub offcut: f32,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SpoolPlan {
+    pub spools: Vec<Spool>,
+    pub bars: Vec<StockBar>,
+    pub offcut_total: f32,
+    // Runs whose pipe is too wide for any straight to fit the envelope; they get no spools.
+    pub untransportable: Vec<usize>,
+}
+
+impl SpoolPlan {
+    pub fn to_json(&self) -> GenError<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    // Share of the purchased stock that ends up in spools.
+    pub fn utilisation(&self, stock_length: f32) -> f32 {
+        let bought = self.bars.len() as f32 * stock_length;
+        if bought > 0.0 {
+            1.0 - self.offcut_total / bought
+        } else {
+            1.0
+        }
+    }
+}
+
+fn extent(points: &[Vector3], diameter: f32) -> Vector3 {
+    let mut min = points[0];
+    let mut max = points[0];
+    for p in points.iter() {
+        min = Vector3::new(min.x.min(p.x), min.y.min(p.y), min.z.min(p.z));
+        max = Vector3::new(max.x.max(p.x), max.y.max(p.y), max.z.max(p.z));
+    }
+    max - min + Vector3::new(diameter, diameter, diameter)
+}
+
+fn sorted(v: Vector3) -> [f32; 3] {
+    let mut dims = [v.x, v.y, v.z];
+    dims.sort_by(|a, b| b.total_cmp(a));
+    dims
+}
+
+fn fits(extent: Vector3, envelope: Vector3) -> bool {
+    let (extent, envelope) = (sorted(extent), sorted(envelope));
+    (0..3).all(|i| extent[i] <= envelope[i] + 1e-4)
+}
+
+// Splits one route into spools. Spools break at fittings wherever possible; a straight
+// longer than the envelope gets extra joints along its length. `None` when the pipe is as
+// wide as the envelope is long.
+fn split_run(
+    run: usize,
+    spec: &PipeSpec,
+    path: &PathfindingPath,
+    config: &SpoolConfig,
+) -> Option<Vec<Spool>> {
+    let path = path.merge_collinear(1e-4);
+    let max_straight = sorted(config.envelope)[0] - spec.diameter;
+    if max_straight <= 0.0 {
+        return None;
+    }
+    let mut points: Vec<Vector3> = path.points.iter().take(1).copied().collect();
+    for pair in path.points.windows(2) {
+        let length = (pair[1] - pair[0]).length();
+        let chunks = (length / max_straight).ceil().max(1.0) as usize;
+        for k in 1..=chunks {
+            points.push(pair[0] + (k as f32 / chunks as f32) * (pair[1] - pair[0]));
+        }
+    }
+
+    let mut groups: Vec<Vec<Vector3>> = vec![];
+    let mut current: Vec<Vector3> = points.iter().take(1).copied().collect();
+    for &point in points.iter().skip(1) {
+        current.push(point);
+        if current.len() > 2 && !fits(extent(&current, spec.diameter), config.envelope) {
+            current.pop();
+            let joint = *current.last().unwrap();
+            groups.push(std::mem::replace(&mut current, vec![joint, point]));
+        }
+    }
+    if current.len() > 1 {
+        groups.push(current);
+    }
+
+    let spools = groups
+        .into_iter()
+        .map(|points| {
+            let origin = Vector3::new(
+                points.iter().map(|p| p.x).fold(f32::INFINITY, f32::min),
+                points.iter().map(|p| p.y).fold(f32::INFINITY, f32::min),
+                points.iter().map(|p| p.z).fold(f32::INFINITY, f32::min),
+            );
+            let elbows = points
+                .windows(3)
+                .filter(|w| ((w[1] - w[0]).norm() * (w[2] - w[1]).norm()) < 1.0 - 1e-4)
+                .count();
+            let mut pieces = vec![];
+            for pair in points.windows(2) {
+                let mut length = (pair[1] - pair[0]).length();
+                while length > config.stock_length {
+                    pieces.push(config.stock_length);
+                    length -= config.stock_length;
+                }
+                if length > 1e-4 {
+                    pieces.push(length);
+                }
+            }
+            let size = extent(&points, spec.diameter);
+            Spool {
+                run,
+                material: spec.material.clone(),
+                diameter: spec.diameter,
+                origin: [origin.x, origin.y, origin.z],
+                points: points
+                    .iter()
+                    .map(|&p| {
+                        let p = p - origin;
+                        [p.x, p.y, p.z]
+                    })
+                    .collect(),
+                extent: [size.x, size.y, size.z],
+                elbows,
+                pieces,
+            }
+        })
+        .collect();
+    Some(spools)
+}
+
+// Best-fit decreasing: longest pieces first, each into the bar it leaves the least of.
+fn cut_list(spools: &[Spool], config: &SpoolConfig) -> Vec<StockBar> {
+    let mut bars: Vec<StockBar> = vec![];
+    let mut cuts: Vec<(usize, usize, f32)> = spools
+        .iter()
+        .enumerate()
+        .flat_map(|(s, spool)| {
+            spool
+                .pieces
+                .iter()
+                .enumerate()
+                .map(move |(p, &length)| (s, p, length))
+        })
+        .collect();
+    cuts.sort_by(|a, b| b.2.total_cmp(&a.2));
+    for (s, p, length) in cuts {
+        let spool = &spools[s];
+        // The last cut of a bar does not need a kerf behind it.
+        let best = bars
+            .iter_mut()
+            .filter(|bar| bar.material == spool.material && bar.diameter == spool.diameter)
+            .filter(|bar| bar.offcut + 1e-5 >= length + config.kerf)
+            .min_by(|a, b| a.offcut.total_cmp(&b.offcut));
+        let bar = match best {
+            Some(bar) => bar,
+            None => {
+                bars.push(StockBar {
+                    material: spool.material.clone(),
+                    diameter: spool.diameter,
+                    cuts: vec![],
+                    offcut: config.stock_length + config.kerf,
+                });
+                bars.last_mut().unwrap()
+            }
+        };
+        bar.offcut -= length + config.kerf;
+        bar.cuts.push(Cut {
+            spool: s,
+            piece: p,
+            length,
+        });
+    }
+    // Separating the offcut takes one more kerf unless the bar was used up exactly.
+    for bar in bars.iter_mut() {
+        bar.offcut = (bar.offcut - config.kerf).max(0.0);
+    }
+    bars
+}
+
+pub fn plan_spools(runs: &[(PipeSpec, PathfindingPath)], config: &SpoolConfig) -> SpoolPlan {
+    let mut spools: Vec<Spool> = vec![];
+    let mut untransportable = vec![];
+    for (run, (spec, path)) in runs.iter().enumerate() {
+        match split_run(run, spec, path, config) {
+            Some(run_spools) => spools.extend(run_spools),
+            None => untransportable.push(run),
+        }
+    }
+    let bars = cut_list(&spools, config);
+    let offcut_total = bars.iter().map(|bar| bar.offcut).sum();
+    SpoolPlan {
+        spools,
+        bars,
+        offcut_total,
+        untransportable,
+    }
+}
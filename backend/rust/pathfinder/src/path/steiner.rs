@@ -0,0 +1,177 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::math::vector::Vector3;
+
+use super::{count_bends, Cell, CostModel, Grid, PathfindingPath, SearchParams};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NetworkNodeKind {
+    Source,
+    Terminal(usize),
+    Tee,
+    Cross,
+}
+
+#[derive(Debug, Clone)]
+pub struct NetworkNode {
+    pub kind: NetworkNodeKind,
+    pub cell: Cell,
+    pub position: Vector3,
+}
+
+#[derive(Debug)]
+pub struct NetworkEdge {
+    pub from: usize,
+    pub to: usize,
+    pub cells: Vec<Cell>,
+    pub path: PathfindingPath,
+}
+
+#[derive(Debug, Default)]
+pub struct PipeNetwork {
+    pub nodes: Vec<NetworkNode>,
+    pub edges: Vec<NetworkEdge>,
+    // Indices of terminals that could not be connected to the network.
+    pub unreached: Vec<usize>,
+}
+
+impl PipeNetwork {
+    pub fn total_length(&self, cell_size: f32) -> f32 {
+        self.edges
+            .iter()
+            .map(|edge| (edge.cells.len() - 1) as f32 * cell_size)
+            .sum()
+    }
+
+    pub fn bends(&self) -> usize {
+        self.edges.iter().map(|edge| count_bends(&edge.cells)).sum()
+    }
+
+    pub fn junctions(&self) -> impl Iterator<Item = &NetworkNode> {
+        self.nodes
+            .iter()
+            .filter(|node| matches!(node.kind, NetworkNodeKind::Tee | NetworkNodeKind::Cross))
+    }
+}
+
+impl Grid {
+    // Rectilinear Steiner tree by repeated shortest paths: the tree grows from the source and
+    // every search attaches the cheapest remaining terminal to any cell already in the tree.
+    pub fn steiner_tree<C: CostModel + ?Sized>(
+        &self,
+        source: Vector3,
+        terminals: &[Vector3],
+        cost: &C,
+        params: &SearchParams,
+    ) -> Option<PipeNetwork> {
+        let source = self.cell_at(source).filter(|&cell| self.is_free(cell))?;
+        let mut network = PipeNetwork::default();
+        let mut remaining: HashMap<Cell, Vec<usize>> = HashMap::new();
+        for (i, &terminal) in terminals.iter().enumerate() {
+            match self.cell_at(terminal).filter(|&cell| self.is_free(cell)) {
+                Some(cell) => remaining.entry(cell).or_default().push(i),
+                None => network.unreached.push(i),
+            }
+        }
+
+        let mut tree: Vec<Cell> = vec![source];
+        let mut in_tree: HashSet<Cell> = tree.iter().copied().collect();
+        let mut adjacency: HashMap<Cell, Vec<Cell>> = HashMap::new();
+        let mut connected: HashMap<Cell, Vec<usize>> = HashMap::new();
+        if let Some(indices) = remaining.remove(&source) {
+            connected.insert(source, indices);
+        }
+        while !remaining.is_empty() {
+            let goals: Vec<Cell> = remaining.keys().copied().collect();
+            let Some(cells) = self.search(&tree, &goals, cost, params) else {
+                for (_, indices) in remaining.drain() {
+                    network.unreached.extend(indices);
+                }
+                break;
+            };
+            for pair in cells.windows(2) {
+                let (a, b) = (pair[0], pair[1]);
+                for (from, to) in [(a, b), (b, a)] {
+                    let neighbors = adjacency.entry(from).or_default();
+                    if !neighbors.contains(&to) {
+                        neighbors.push(to);
+                    }
+                }
+                if in_tree.insert(b) {
+                    tree.push(b);
+                }
+            }
+            let reached = *cells.last().unwrap();
+            connected.insert(reached, remaining.remove(&reached).unwrap());
+        }
+        network.unreached.sort();
+
+        // Every terminal gets a node, even where several share a cell or sit at the source.
+        let mut kinds = vec![(source, NetworkNodeKind::Source)];
+        let mut terminal_cells: Vec<(Cell, usize)> = connected
+            .iter()
+            .flat_map(|(&cell, indices)| indices.iter().map(move |&i| (cell, i)))
+            .collect();
+        terminal_cells.sort_by_key(|&(_, i)| i);
+        for (cell, i) in terminal_cells {
+            kinds.push((cell, NetworkNodeKind::Terminal(i)));
+        }
+        for &cell in tree.iter() {
+            let degree = adjacency.get(&cell).map_or(0, |n| n.len());
+            if !connected.contains_key(&cell) && cell != source && degree >= 3 {
+                let kind = if degree == 3 {
+                    NetworkNodeKind::Tee
+                } else {
+                    NetworkNodeKind::Cross
+                };
+                kinds.push((cell, kind));
+            }
+        }
+        // Edges attach to the first node in each cell; nodes sharing it have no edges.
+        let mut node_index: HashMap<Cell, usize> = HashMap::new();
+        for (cell, kind) in kinds {
+            node_index.entry(cell).or_insert(network.nodes.len());
+            network.nodes.push(NetworkNode {
+                kind,
+                cell,
+                position: self.cell_center(cell),
+            });
+        }
+
+        // Split the tree into edges between consecutive nodes.
+        let mut visited: HashSet<(Cell, Cell)> = HashSet::new();
+        for from in 0..network.nodes.len() {
+            let start = network.nodes[from].cell;
+            for &first in adjacency.get(&start).into_iter().flatten() {
+                if visited.contains(&(start, first)) {
+                    continue;
+                }
+                let mut cells = vec![start, first];
+                loop {
+                    let current = cells[cells.len() - 1];
+                    let previous = cells[cells.len() - 2];
+                    if node_index.contains_key(&current) {
+                        break;
+                    }
+                    let next = adjacency[&current]
+                        .iter()
+                        .copied()
+                        .find(|&next| next != previous)
+                        .unwrap();
+                    cells.push(next);
+                }
+                for pair in cells.windows(2) {
+                    visited.insert((pair[0], pair[1]));
+                    visited.insert((pair[1], pair[0]));
+                }
+                network.edges.push(NetworkEdge {
+                    from,
+                    to: node_index[cells.last().unwrap()],
+                    path: self.cells_to_path(&cells),
+                    cells,
+                });
+            }
+        }
+        Some(network)
+    }
+}
@@ -0,0 +1,144 @@
+use crate::math::vector::Vector3;
+
+use super::{direction_vector, Cell, Grid, PathfindingPath, RunConstraint, DIRECTIONS};
+
+#[derive(Debug, Clone, Copy)]
+pub struct Endpoint {
+    pub position: Vector3,
+    // Direction in which the pipe leaves the connection point, if known.
+    pub direction: Option<Vector3>,
+    // Straight length required from the connection point before the first bend, typically a
+    // multiple of the pipe diameter for pumps, meters and valves.
+    pub min_straight: f32,
+}
+
+impl Endpoint {
+    pub fn at(position: Vector3) -> Self {
+        Self {
+            position,
+            direction: None,
+            min_straight: 0.0,
+        }
+    }
+}
+
+impl From<Vector3> for Endpoint {
+    fn from(position: Vector3) -> Self {
+        Endpoint::at(position)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SnappedEndpoint {
+    pub cell: Cell,
+    // Points from the connection point to the centre of `cell`, empty when they coincide.
+    pub escape: Vec<Vector3>,
+}
+
+impl SnappedEndpoint {
+    pub fn escape_length(&self) -> f32 {
+        self.escape
+            .windows(2)
+            .map(|pair| (pair[1] - pair[0]).length())
+            .sum()
+    }
+
+    // Length of the escape that runs straight out of the connector. An escape that jogs
+    // onto the cell centre bends right away, so none of it counts.
+    fn straight_escape(&self, endpoint: &Endpoint) -> f32 {
+        let (Some(direction), [from, to]) = (endpoint.direction, self.escape.as_slice()) else {
+            return 0.0;
+        };
+        let leg = *to - *from;
+        let along = leg * direction_vector(nearest_direction(direction));
+        if along > 0.0 && (leg.length() - along).abs() < 1e-5 {
+            along
+        } else {
+            0.0
+        }
+    }
+
+    // Straight run still needed on the grid once the escape segment has been laid, in the
+    // direction of travel away from the endpoint.
+    pub fn run_constraint(&self, endpoint: &Endpoint, cell_size: f32) -> RunConstraint {
+        let remaining = (endpoint.min_straight - self.straight_escape(endpoint)).max(0.0);
+        RunConstraint {
+            dir: endpoint.direction.map(nearest_direction),
+            min_run: (remaining / cell_size - 1e-4).ceil() as usize,
+        }
+    }
+}
+
+// Axis-aligned direction closest to `direction`.
+pub fn nearest_direction(direction: Vector3) -> usize {
+    (0..DIRECTIONS.len())
+        .max_by(|&a, &b| {
+            (direction_vector(a) * direction).total_cmp(&(direction_vector(b) * direction))
+        })
+        .unwrap()
+}
+
+impl Grid {
+    // Resolves an endpoint to a free cell. Endpoints on equipment usually sit inside an
+    // obstacle, so the nearest free cell is searched for, along the connector direction when
+    // one is given, no further than `max_distance` away.
+    pub fn snap(&self, endpoint: &Endpoint, max_distance: f32) -> Option<SnappedEndpoint> {
+        let origin = self.cell_at(endpoint.position)?;
+        let target = match endpoint.direction {
+            _ if self.is_free(origin) => origin,
+            Some(direction) => {
+                let dir = nearest_direction(direction);
+                let steps = (max_distance / self.cell_size()).ceil() as usize;
+                let mut cell = origin;
+                let mut found = None;
+                for _ in 0..steps {
+                    cell = self.neighbor(cell, dir)?;
+                    if self.is_free(cell) {
+                        found = Some(cell);
+                        break;
+                    }
+                }
+                found?
+            }
+            None => {
+                let radius = (max_distance / self.cell_size()).ceil() as usize;
+                self.cells_within(origin, radius)
+                    .filter(|&cell| self.is_free(cell))
+                    .map(|cell| (cell, (self.cell_center(cell) - endpoint.position).length()))
+                    .filter(|&(_, distance)| distance <= max_distance)
+                    .min_by(|a, b| a.1.total_cmp(&b.1))?
+                    .0
+            }
+        };
+        let center = self.cell_center(target);
+        let mut escape = vec![endpoint.position];
+        if let Some(direction) = endpoint.direction.filter(|_| target != origin) {
+            // Leave straight along the connector, then jog onto the cell centre.
+            let axis = direction_vector(nearest_direction(direction));
+            let run = endpoint.position + ((center - endpoint.position) * axis) * axis;
+            escape.push(run);
+        }
+        escape.push(center);
+        escape.dedup_by(|a, b| (*a - *b).length() < 1e-5);
+        if escape.len() < 2 {
+            escape.clear();
+        }
+        Some(SnappedEndpoint {
+            cell: target,
+            escape,
+        })
+    }
+}
+
+// Joins the escape segments of both endpoints onto a route between their cells.
+pub fn attach_escapes(
+    start: &SnappedEndpoint,
+    path: PathfindingPath,
+    goal: &SnappedEndpoint,
+) -> PathfindingPath {
+    let mut points = start.escape.clone();
+    points.pop();
+    points.extend(path.points);
+    points.extend(goal.escape.iter().rev().skip(1));
+    PathfindingPath { points }
+}
@@ -0,0 +1,303 @@
+use std::{
+    cmp::Ordering,
+    collections::{BinaryHeap, HashMap},
+};
+
+use crate::math::vector::Vector3;
+
+use super::{PathfindingPath, Profile};
+
+#[derive(Debug, Clone)]
+pub struct Tray {
+    pub id: String,
+    pub path: PathfindingPath,
+    pub profile: Profile,
+}
+
+#[derive(Debug, Clone)]
+pub struct Cable {
+    pub id: String,
+    pub from: Vector3,
+    pub to: Vector3,
+    pub diameter: f32,
+}
+
+impl Cable {
+    pub fn area(&self) -> f32 {
+        std::f32::consts::PI * self.diameter * self.diameter / 4.0
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct TrayConfig {
+    // Share of the tray cross-section that cables may occupy.
+    pub max_fill: f32,
+    // Longest drop from a cable end to the nearest tray.
+    pub max_drop: f32,
+}
+
+impl Default for TrayConfig {
+    fn default() -> Self {
+        Self {
+            max_fill: 0.4,
+            max_drop: 3.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TraySegmentFill {
+    pub tray: usize,
+    pub from: Vector3,
+    pub to: Vector3,
+    pub used_area: f32,
+    // Percentage of the tray cross-section occupied by cables.
+    pub fill: f32,
+    pub cables: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct CableRoute {
+    pub id: String,
+    pub path: PathfindingPath,
+    // Off-tray lengths at the start and end of the cable.
+    pub drops: [f32; 2],
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CableFailure {
+    NoTrayInReach,
+    NoCapacity,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct CableLayout {
+    pub segments: Vec<TraySegmentFill>,
+    pub cables: Vec<CableRoute>,
+    pub failed: Vec<(String, CableFailure)>,
+}
+
+struct Edge {
+    a: usize,
+    b: usize,
+    tray: usize,
+    length: f32,
+    capacity: f32,
+}
+
+#[derive(Default)]
+struct TrayGraph {
+    positions: Vec<Vector3>,
+    lookup: HashMap<[i64; 3], usize>,
+    edges: Vec<Edge>,
+    adjacency: Vec<Vec<usize>>,
+}
+
+impl TrayGraph {
+    // Nodes closer than a millimetre are merged so that trays meeting end to end connect.
+    fn node(&mut self, position: Vector3) -> usize {
+        let key = [position.x, position.y, position.z].map(|v| (v * 1000.0).round() as i64);
+        *self.lookup.entry(key).or_insert_with(|| {
+            self.positions.push(position);
+            self.adjacency.push(vec![]);
+            self.positions.len() - 1
+        })
+    }
+}
+
+struct Visit {
+    cost: f32,
+    node: usize,
+}
+
+impl PartialEq for Visit {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Visit {}
+
+impl PartialOrd for Visit {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Visit {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.cost.total_cmp(&self.cost)
+    }
+}
+
+// Parameter of the point on `a`-`b` closest to `p`.
+fn project(p: Vector3, a: Vector3, b: Vector3) -> f32 {
+    let d = b - a;
+    let length = d * d;
+    if length < 1e-12 {
+        return 0.0;
+    }
+    (((p - a) * d) / length).clamp(0.0, 1.0)
+}
+
+// Routes cables over the tray network, largest first, so that no tray segment is filled
+// beyond `max_fill`. Each cable drops from its ends straight to the nearest tray.
+pub fn route_cables(trays: &[Tray], cables: &[Cable], config: &TrayConfig) -> CableLayout {
+    let segments: Vec<(usize, Vector3, Vector3)> = trays
+        .iter()
+        .enumerate()
+        .flat_map(|(t, tray)| tray.path.points.windows(2).map(move |w| (t, w[0], w[1])))
+        .collect();
+
+    // Where each cable end meets the trays: segment, parameter and drop length.
+    let attach = |p: Vector3| {
+        segments
+            .iter()
+            .enumerate()
+            .map(|(s, &(_, a, b))| {
+                let t = project(p, a, b);
+                (s, t, (a + t * (b - a) - p).length())
+            })
+            .filter(|&(_, _, drop)| drop <= config.max_drop)
+            .min_by(|x, y| x.2.total_cmp(&y.2))
+    };
+    let attachments: Vec<Option<[(usize, f32, f32); 2]>> = cables
+        .iter()
+        .map(|cable| Some([attach(cable.from)?, attach(cable.to)?]))
+        .collect();
+
+    // Split every segment at tray junctions and cable attachments.
+    let mut splits: Vec<Vec<f32>> = vec![vec![0.0, 1.0]; segments.len()];
+    for (s, &(_, a, b)) in segments.iter().enumerate() {
+        for &(_, c, d) in segments.iter() {
+            for p in [c, d] {
+                let t = project(p, a, b);
+                if (a + t * (b - a) - p).length() < 1e-3 {
+                    splits[s].push(t);
+                }
+            }
+        }
+    }
+    for ends in attachments.iter().flatten() {
+        for &(s, t, _) in ends {
+            splits[s].push(t);
+        }
+    }
+    let mut graph = TrayGraph::default();
+    for (s, &(tray, a, b)) in segments.iter().enumerate() {
+        let splits = &mut splits[s];
+        splits.sort_by(|x, y| x.total_cmp(y));
+        // Splits are merged by node rather than by distance along the segment, so every
+        // attachment later finds the node it was snapped to.
+        let mut nodes: Vec<usize> = splits
+            .iter()
+            .map(|&t| graph.node(a + t * (b - a)))
+            .collect();
+        nodes.dedup();
+        let profile = trays[tray].profile;
+        for pair in nodes.windows(2) {
+            let (na, nb) = (pair[0], pair[1]);
+            let (p, q) = (graph.positions[na], graph.positions[nb]);
+            graph.adjacency[na].push(graph.edges.len());
+            graph.adjacency[nb].push(graph.edges.len());
+            graph.edges.push(Edge {
+                a: na,
+                b: nb,
+                tray,
+                length: (q - p).length(),
+                capacity: profile.width * profile.height * config.max_fill,
+            });
+        }
+    }
+
+    let mut used = vec![0.0_f32; graph.edges.len()];
+    let mut carried: Vec<Vec<String>> = vec![vec![]; graph.edges.len()];
+    let mut layout = CableLayout::default();
+    let mut order: Vec<usize> = (0..cables.len()).collect();
+    order.sort_by(|&x, &y| cables[y].area().total_cmp(&cables[x].area()));
+    for c in order {
+        let cable = &cables[c];
+        let Some([start, end]) = attachments[c] else {
+            layout
+                .failed
+                .push((cable.id.clone(), CableFailure::NoTrayInReach));
+            continue;
+        };
+        let point = |(s, t, _): (usize, f32, f32)| {
+            let (_, a, b) = segments[s];
+            a + t * (b - a)
+        };
+        let (source, target) = (graph.node(point(start)), graph.node(point(end)));
+
+        let mut cost = vec![f32::INFINITY; graph.positions.len()];
+        let mut via: Vec<Option<usize>> = vec![None; graph.positions.len()];
+        let mut heap = BinaryHeap::from([Visit {
+            cost: 0.0,
+            node: source,
+        }]);
+        cost[source] = 0.0;
+        while let Some(Visit { cost: c, node }) = heap.pop() {
+            if node == target || c > cost[node] {
+                continue;
+            }
+            for &e in graph.adjacency[node].iter() {
+                let edge = &graph.edges[e];
+                if used[e] + cable.area() > edge.capacity {
+                    continue;
+                }
+                let next = if edge.a == node { edge.b } else { edge.a };
+                if c + edge.length < cost[next] {
+                    cost[next] = c + edge.length;
+                    via[next] = Some(e);
+                    heap.push(Visit {
+                        cost: cost[next],
+                        node: next,
+                    });
+                }
+            }
+        }
+        if !cost[target].is_finite() {
+            layout
+                .failed
+                .push((cable.id.clone(), CableFailure::NoCapacity));
+            continue;
+        }
+
+        let mut points = vec![cable.to, graph.positions[target]];
+        let mut node = target;
+        while let Some(e) = via[node] {
+            used[e] += cable.area();
+            carried[e].push(cable.id.clone());
+            let edge = &graph.edges[e];
+            node = if edge.a == node { edge.b } else { edge.a };
+            points.push(graph.positions[node]);
+        }
+        points.push(cable.from);
+        points.reverse();
+        points.dedup_by(|x, y| (*x - *y).length() < 1e-5);
+        layout.cables.push(CableRoute {
+            id: cable.id.clone(),
+            path: PathfindingPath { points }.merge_collinear(1e-4),
+            drops: [start.2, end.2],
+        });
+    }
+
+    layout.segments = graph
+        .edges
+        .iter()
+        .zip(used)
+        .zip(carried)
+        .map(|((edge, used_area), cables)| {
+            let profile = trays[edge.tray].profile;
+            TraySegmentFill {
+                tray: edge.tray,
+                from: graph.positions[edge.a],
+                to: graph.positions[edge.b],
+                used_area,
+                fill: 100.0 * used_area / (profile.width * profile.height),
+                cables,
+            }
+        })
+        .collect();
+    layout
+}
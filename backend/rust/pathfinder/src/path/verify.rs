@@ -0,0 +1,61 @@
+use crate::{math::vector::Vector3, scene::Scene};
+
+use super::{PathfindingPath, PenetrationPolicy};
+
+#[derive(Debug, Clone)]
+pub struct Violation {
+    pub segment: usize,
+    // Index into `Scene::obstacles`.
+    pub element: usize,
+    pub class: Option<String>,
+    pub global_id: Option<String>,
+    // Closest (or deepest) point of the segment centreline.
+    pub position: Vector3,
+    // How far the pipe wall plus clearance reaches into the element.
+    pub depth: f32,
+    // The pipe itself touches the element, not only its clearance zone.
+    pub collides: bool,
+    pub penetrable: bool,
+}
+
+impl PathfindingPath {
+    // Checks every segment, swept as a cylinder of `diameter` grown by `clearance`, against
+    // every obstacle box. Bends are treated as spheres of the same radius.
+    pub fn verify(
+        &self,
+        scene: &Scene,
+        diameter: f32,
+        clearance: f32,
+        policy: &PenetrationPolicy,
+    ) -> Vec<Violation> {
+        let radius = diameter / 2.0;
+        let reach = radius + clearance;
+        let mut violations = vec![];
+        for (segment, pair) in self.points.windows(2).enumerate() {
+            let (a, b) = (pair[0], pair[1]);
+            for (element, bbox) in scene.obstacles.iter().enumerate() {
+                if !bbox.is_obstacle() {
+                    continue;
+                }
+                let (distance, t) = match bbox.bounds.segment_depth(a, b) {
+                    Some((inside, t)) => (-inside, t),
+                    None => bbox.bounds.segment_distance(a, b),
+                };
+                if distance >= reach {
+                    continue;
+                }
+                violations.push(Violation {
+                    segment,
+                    element,
+                    class: bbox.class.clone(),
+                    global_id: bbox.global_id.clone(),
+                    position: a + t * (b - a),
+                    depth: reach - distance,
+                    collides: distance < radius,
+                    penetrable: policy.cost(bbox).is_some(),
+                });
+            }
+        }
+        violations
+    }
+}
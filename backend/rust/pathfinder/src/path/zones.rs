@@ -0,0 +1,160 @@
+use crate::{
+    math::vector::Vector3,
+    scene::{Bounds, Scene},
+};
+
+use super::{Cell, CostModel, Grid};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ZoneKind {
+    CeilingVoid,
+    Corridor,
+}
+
+#[derive(Debug, Clone)]
+pub struct ServiceZone {
+    pub kind: ZoneKind,
+    pub bounds: Bounds,
+    // Index into `Scene::obstacles` of the covering or space the zone was derived from.
+    pub source: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct ZoneConfig {
+    // Case-insensitive fragments of `IfcSpace` names that mark a corridor.
+    pub corridor_names: Vec<String>,
+    pub min_void_depth: f32,
+    pub max_void_depth: f32,
+    // Cost multipliers for cells inside each kind of zone, below 1 to attract routes.
+    pub ceiling_void_factor: f32,
+    pub corridor_factor: f32,
+}
+
+impl Default for ZoneConfig {
+    fn default() -> Self {
+        Self {
+            corridor_names: ["corridor", "hall", "flur", "korytarz"]
+                .into_iter()
+                .map(String::from)
+                .collect(),
+            min_void_depth: 0.05,
+            max_void_depth: 1.5,
+            ceiling_void_factor: 0.6,
+            corridor_factor: 0.8,
+        }
+    }
+}
+
+impl ZoneConfig {
+    fn factor(&self, kind: ZoneKind) -> f32 {
+        match kind {
+            ZoneKind::CeilingVoid => self.ceiling_void_factor,
+            ZoneKind::Corridor => self.corridor_factor,
+        }
+    }
+}
+
+pub fn service_zones(scene: &Scene, config: &ZoneConfig) -> Vec<ServiceZone> {
+    let mut zones = vec![];
+    for (source, covering) in scene.obstacles.iter().enumerate() {
+        if !covering.is_class("IfcCovering") {
+            continue;
+        }
+        // The void runs from the top of the ceiling to the soffit of the lowest slab above it.
+        let ceiling = covering.bounds;
+        let soffit = scene
+            .obstacles
+            .iter()
+            .filter(|slab| {
+                let depth = slab.bounds.min.z - ceiling.max.z;
+                let overlaps = slab.bounds.min.x < ceiling.max.x
+                    && slab.bounds.max.x > ceiling.min.x
+                    && slab.bounds.min.y < ceiling.max.y
+                    && slab.bounds.max.y > ceiling.min.y;
+                slab.is_class("IfcSlab")
+                    && overlaps
+                    && depth >= config.min_void_depth
+                    && depth <= config.max_void_depth
+            })
+            .min_by(|a, b| a.bounds.min.z.total_cmp(&b.bounds.min.z));
+        if let Some(slab) = soffit {
+            // Columns from the ceiling up to the soffit over each footprint; the void is
+            // where they overlap.
+            let column = |footprint: &Bounds| {
+                Bounds::new(
+                    Vector3::new(footprint.min.x, footprint.min.y, ceiling.max.z),
+                    Vector3::new(footprint.max.x, footprint.max.y, slab.bounds.min.z),
+                )
+            };
+            if let Some(bounds) = column(&ceiling).intersection(&column(&slab.bounds)) {
+                zones.push(ServiceZone {
+                    kind: ZoneKind::CeilingVoid,
+                    bounds,
+                    source,
+                });
+            }
+        }
+    }
+    for (source, space) in scene.obstacles.iter().enumerate() {
+        let Some(name) = space.name.as_ref().filter(|_| space.is_class("IfcSpace")) else {
+            continue;
+        };
+        let name = name.to_lowercase();
+        if config
+            .corridor_names
+            .iter()
+            .any(|fragment| name.contains(&fragment.to_lowercase()))
+        {
+            zones.push(ServiceZone {
+                kind: ZoneKind::Corridor,
+                bounds: space.bounds,
+                source,
+            });
+        }
+    }
+    zones
+}
+
+// Per-cell cost multipliers; where zones overlap the strongest discount wins.
+pub struct AttractionField {
+    factors: Vec<f32>,
+    min_factor: f32,
+}
+
+impl AttractionField {
+    pub fn build(grid: &Grid, zones: &[ServiceZone], config: &ZoneConfig) -> Self {
+        let mut factors = vec![1.0_f32; grid.len()];
+        for zone in zones {
+            let factor = config.factor(zone.kind);
+            for cell in grid.cells_in(&zone.bounds) {
+                let i = grid.index(cell);
+                factors[i] = factors[i].min(factor);
+            }
+        }
+        let min_factor = factors.iter().copied().fold(1.0, f32::min);
+        Self {
+            factors,
+            min_factor,
+        }
+    }
+
+    pub fn factor(&self, grid: &Grid, cell: Cell) -> f32 {
+        self.factors[grid.index(cell)]
+    }
+}
+
+pub struct Attracted<'a, C: CostModel> {
+    pub inner: C,
+    pub field: &'a AttractionField,
+}
+
+impl<C: CostModel> CostModel for Attracted<'_, C> {
+    fn step_cost(&self, grid: &Grid, from: Cell, to: Cell) -> Option<f32> {
+        let cost = self.inner.step_cost(grid, from, to)?;
+        Some(cost * self.field.factor(grid, to))
+    }
+
+    fn lower_bound(&self, grid: &Grid) -> f32 {
+        self.inner.lower_bound(grid) * self.field.min_factor
+    }
+}
@@ -7,8 +7,13 @@ use crate::{
     utility::GenError,
 };
 
+mod diff;
+mod edit;
 mod json;
 
+pub use diff::*;
+pub use edit::*;
+
 pub struct Scene {
     pub room: BBox,
     pub obstacles: Vec<BBox>,
@@ -47,6 +52,25 @@ impl Bounds {
         Bounds::new(min, max)
     }
 
+    pub fn expand(&self, margin: f32) -> Bounds {
+        let margin = Vector3::new(margin, margin, margin);
+        Bounds::new(self.min - margin, self.max + margin)
+    }
+
+    pub fn intersection(&self, other: &Bounds) -> Option<Bounds> {
+        let min = Vector3::new(
+            self.min.x.max(other.min.x),
+            self.min.y.max(other.min.y),
+            self.min.z.max(other.min.z),
+        );
+        let max = Vector3::new(
+            self.max.x.min(other.max.x),
+            self.max.y.min(other.max.y),
+            self.max.z.min(other.max.z),
+        );
+        (min.x < max.x && min.y < max.y && min.z < max.z).then_some(Bounds::new(min, max))
+    }
+
     pub fn contains(&self, other: &Bounds) -> bool {
         self.min.x < other.max.x
             && self.max.x > other.min.x
@@ -110,6 +134,132 @@ impl Bounds {
             && point.z <= self.max.z
     }
 
+    // Parameter range `[t0, t1]` of the segment `a + t * (b - a)` that lies inside the bounds.
+    pub fn intersect_segment(&self, a: Vector3, b: Vector3) -> Option<(f32, f32)> {
+        let d = b - a;
+        let mut t0 = 0.0_f32;
+        let mut t1 = 1.0_f32;
+        for (origin, dir, min, max) in [
+            (a.x, d.x, self.min.x, self.max.x),
+            (a.y, d.y, self.min.y, self.max.y),
+            (a.z, d.z, self.min.z, self.max.z),
+        ] {
+            if dir.abs() < f32::EPSILON {
+                if origin < min || origin > max {
+                    return None;
+                }
+                continue;
+            }
+            let (near, far) = {
+                let near = (min - origin) / dir;
+                let far = (max - origin) / dir;
+                if near < far {
+                    (near, far)
+                } else {
+                    (far, near)
+                }
+            };
+            t0 = t0.max(near);
+            t1 = t1.min(far);
+            if t0 > t1 {
+                return None;
+            }
+        }
+        Some((t0, t1))
+    }
+
+    // Exact distance from the segment `a`-`b` to the bounds and the segment parameter of the
+    // closest point. The squared distance is quadratic in `t` between the parameters where
+    // the segment crosses a face plane, so each piece is minimised in closed form.
+    pub fn segment_distance(&self, a: Vector3, b: Vector3) -> (f32, f32) {
+        let d = b - a;
+        let axes = [
+            (a.x, d.x, self.min.x, self.max.x),
+            (a.y, d.y, self.min.y, self.max.y),
+            (a.z, d.z, self.min.z, self.max.z),
+        ];
+        let mut breaks = vec![0.0_f32, 1.0];
+        for &(origin, dir, min, max) in axes.iter() {
+            if dir.abs() > f32::EPSILON {
+                for plane in [min, max] {
+                    let t = (plane - origin) / dir;
+                    if t > 0.0 && t < 1.0 {
+                        breaks.push(t);
+                    }
+                }
+            }
+        }
+        breaks.sort_by(|a, b| a.total_cmp(b));
+        let distance_sq = |t: f32| {
+            axes.iter()
+                .map(|&(origin, dir, min, max)| {
+                    let p = origin + t * dir;
+                    let outside = (min - p).max(p - max).max(0.0);
+                    outside * outside
+                })
+                .sum::<f32>()
+        };
+        let mut best = (distance_sq(0.0), 0.0);
+        for pair in breaks.windows(2) {
+            let (t0, t1) = (pair[0], pair[1]);
+            let mid = (t0 + t1) / 2.0;
+            // Coefficients of the quadratic valid on this interval.
+            let (mut qa, mut qb) = (0.0, 0.0);
+            for &(origin, dir, min, max) in axes.iter() {
+                let p = origin + mid * dir;
+                let plane = if p < min {
+                    min
+                } else if p > max {
+                    max
+                } else {
+                    continue;
+                };
+                qa += dir * dir;
+                qb += 2.0 * dir * (origin - plane);
+            }
+            let t = if qa > f32::EPSILON {
+                (-qb / (2.0 * qa)).clamp(t0, t1)
+            } else {
+                t0
+            };
+            for t in [t, t1] {
+                let distance = distance_sq(t);
+                if distance < best.0 {
+                    best = (distance, t);
+                }
+            }
+        }
+        (best.0.sqrt(), best.1)
+    }
+
+    // Deepest point of the segment inside the bounds, as the distance to the nearest face and
+    // the segment parameter where it occurs.
+    pub fn segment_depth(&self, a: Vector3, b: Vector3) -> Option<(f32, f32)> {
+        let (t0, t1) = self.intersect_segment(a, b)?;
+        let depth = |t: f32| {
+            let p = a + t * (b - a);
+            (p.x - self.min.x)
+                .min(self.max.x - p.x)
+                .min(p.y - self.min.y)
+                .min(self.max.y - p.y)
+                .min(p.z - self.min.z)
+                .min(self.max.z - p.z)
+        };
+        // The depth is the minimum of linear functions, hence concave along the segment.
+        let (mut lo, mut hi) = (t0, t1);
+        for _ in 0..60 {
+            let m1 = lo + (hi - lo) / 3.0;
+            let m2 = hi - (hi - lo) / 3.0;
+            if depth(m1) < depth(m2) {
+                lo = m1;
+            } else {
+                hi = m2;
+            }
+        }
+        let t = (lo + hi) / 2.0;
+        Some((depth(t).max(0.0), t))
+    }
+
     pub fn midpoint(&self) -> Vector3 {
         (self.min + self.max) / 2.0
     }
@@ -162,9 +312,62 @@ impl<T: Iterator<Item = Bounds>> From<T> for Bounds {
 pub struct BBox {
     pub bounds: Bounds,
     pub class: Option<String>,
+    pub global_id: Option<String>,
+    pub name: Option<String>,
     pub penetrable: Option<bool>,
 }
 
+impl BBox {
+    pub fn is_obstacle(&self) -> bool {
+        if let Some(penetrable) = self.penetrable {
+            if !penetrable {
+                return true;
+            }
+        }
+        if let Some(ifc_class) = &self.class {
+            let string = ifc_class.as_str();
+            match string {
+                "IfcColumn" => true,
+                "IfcSlab" => true,
+                "IfcMember" => false,
+                "IfcSanitaryTerminal" => true,
+                "IfcRoof" => true,
+                "IfcWall" => true,
+                "IfcBeam" => true,
+                "IfcSpace" => false,
+                "IfcCovering" => true,
+                "IfcBuildingElementProxy" => true,
+                "IfcDoor" => false,
+                "IfcWindow" => false,
+                "IfcFurniture" => false,
+                "IfcGrid" => false,
+                "IfcOpeningElement" => false,
+                "IfcStairFlight" => true,
+                "IfcFurnishingElement" => true,
+                "IfcFooting" => true,
+                "IfcWallStandardCase" => true,
+                "IfcRailing" => true,
+                _ => false,
+            }
+        } else {
+            false
+        }
+    }
+
+    pub fn is_class(&self, class: &str) -> bool {
+        self.class.as_deref() == Some(class)
+    }
+
+    // Axis along which the element is thinnest, i.e. the normal of a wall or slab.
+    pub fn thin_axis(&self) -> usize {
+        let dimensions = self.bounds.dimensions();
+        let extents = [dimensions.x, dimensions.y, dimensions.z];
+        (0..3)
+            .min_by(|&a, &b| extents[a].total_cmp(&extents[b]))
+            .unwrap()
+    }
+}
+
 impl From<JsonBBox> for BBox {
     fn from(value: JsonBBox) -> Self {
         Self {
@@ -177,6 +380,8 @@ impl From<JsonBBox> for BBox {
                 ),
             ),
             class: value.r#type,
+            global_id: value.global_id,
+            name: value.name,
             penetrable: value.penetrable,
         }
     }
@@ -217,41 +422,7 @@ impl Scene {
         let obstacles: Vec<_> = self
             .obstacles
             .iter()
-            .filter_map(|obstacle| {
-                if let Some(penetrable) = obstacle.penetrable {
-                    if !penetrable {
-                        return Some(obstacle);
-                    }
-                }
-                if let Some(ifc_class) = &obstacle.class {
-                    let string = ifc_class.as_str();
-                    match string {
-                        "IfcColumn" => Some(obstacle),
-                        "IfcSlab" => Some(obstacle),
-                        "IfcMember" => None,
-                        "IfcSanitaryTerminal" => Some(obstacle),
-                        "IfcRoof" => Some(obstacle),
-                        "IfcWall" => Some(obstacle),
-                        "IfcBeam" => Some(obstacle),
-                        "IfcSpace" => None,
-                        "IfcCovering" => Some(obstacle),
-                        "IfcBuildingElementProxy" => Some(obstacle),
-                        "IfcDoor" => None,
-                        "IfcWindow" => None,
-                        "IfcFurniture" => None,
-                        "IfcGrid" => None,
-                        "IfcOpeningElement" => None,
-                        "IfcStairFlight" => Some(obstacle),
-                        "IfcFurnishingElement" => Some(obstacle),
-                        "IfcFooting" => Some(obstacle),
-                        "IfcWallStandardCase" => Some(obstacle),
-                        "IfcRailing" => Some(obstacle),
-                        _ => None,
-                    }
-                } else {
-                    None
-                }
-            })
+            .filter(|obstacle| obstacle.is_obstacle())
             .cloned()
             .collect();
         obstacles
@@ -0,0 +1,236 @@
+use std::collections::{HashMap, HashSet};
+
+use super::{BBox, Bounds, Scene};
+
+#[derive(Debug, Clone, Copy)]
+pub struct DiffConfig {
+    // Coordinates closer than this count as equal.
+    pub tolerance: f32,
+    // Furthest an element without a GlobalId may move and still be matched to itself.
+    pub max_move: f32,
+}
+
+impl Default for DiffConfig {
+    fn default() -> Self {
+        Self {
+            tolerance: 1e-3,
+            max_move: 1.0,
+        }
+    }
+}
+
+// One difference between two versions of a scene; indices refer to the obstacles of the
+// scene they come from.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ElementDiff {
+    Added {
+        after: usize,
+    },
+    Removed {
+        before: usize,
+    },
+    Changed {
+        before: usize,
+        after: usize,
+        moved: bool,
+        resized: bool,
+        // The class or penetrability differs.
+        reclassified: bool,
+    },
+}
+
+impl ElementDiff {
+    // Space the element occupied in either version.
+    pub fn regions(&self, before: &Scene, after: &Scene) -> Vec<Bounds> {
+        match *self {
+            ElementDiff::Added { after: a } => vec![after.obstacles[a].bounds],
+            ElementDiff::Removed { before: b } => vec![before.obstacles[b].bounds],
+            ElementDiff::Changed {
+                before: b,
+                after: a,
+                ..
+            } => vec![before.obstacles[b].bounds, after.obstacles[a].bounds],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SceneDiff {
+    pub elements: Vec<ElementDiff>,
+    // Matched elements that did not change.
+    pub unchanged: usize,
+}
+
+impl SceneDiff {
+    pub fn is_empty(&self) -> bool {
+        self.elements.is_empty()
+    }
+
+    pub fn added(&self) -> impl Iterator<Item = usize> + '_ {
+        self.elements.iter().filter_map(|diff| match diff {
+            ElementDiff::Added { after } => Some(*after),
+            _ => None,
+        })
+    }
+
+    pub fn removed(&self) -> impl Iterator<Item = usize> + '_ {
+        self.elements.iter().filter_map(|diff| match diff {
+            ElementDiff::Removed { before } => Some(*before),
+            _ => None,
+        })
+    }
+
+    pub fn changed(&self) -> impl Iterator<Item = &ElementDiff> + '_ {
+        self.elements
+            .iter()
+            .filter(|diff| matches!(diff, ElementDiff::Changed { .. }))
+    }
+}
+
+fn close(a: &Bounds, b: &Bounds, tolerance: f32) -> bool {
+    let d = [a.min - b.min, a.max - b.max];
+    d.iter()
+        .all(|d| d.x.abs() <= tolerance && d.y.abs() <= tolerance && d.z.abs() <= tolerance)
+}
+
+fn same_size(a: &Bounds, b: &Bounds, tolerance: f32) -> bool {
+    let d = a.dimensions() - b.dimensions();
+    d.x.abs() <= tolerance && d.y.abs() <= tolerance && d.z.abs() <= tolerance
+}
+
+fn compare(before: usize, a: &BBox, after: usize, b: &BBox, tolerance: f32) -> Option<ElementDiff> {
+    let resized = !same_size(&a.bounds, &b.bounds, tolerance);
+    let moved = !resized && !close(&a.bounds, &b.bounds, tolerance);
+    let reclassified = a.class != b.class || a.penetrable != b.penetrable;
+    (moved || resized || reclassified).then_some(ElementDiff::Changed {
+        before,
+        after,
+        moved,
+        resized,
+        reclassified,
+    })
+}
+
+fn trusted_id<'a>(bbox: &'a BBox, repeated: &HashSet<&str>) -> Option<&'a str> {
+    bbox.global_id
+        .as_deref()
+        .filter(|id| !repeated.contains(id))
+}
+
+impl Scene {
+    // Differences from `self` to `other`. Elements are matched by GlobalId; those without
+    // one are matched to an element of the same class at the same place, then to the
+    // nearest one of that class that either has the same size within `max_move` or
+    // overlaps it. Exports sometimes repeat a GlobalId, so an id found more than once in
+    // either scene is ignored and its elements are matched like those without one.
+    pub fn diff(&self, other: &Scene, config: &DiffConfig) -> SceneDiff {
+        let tolerance = config.tolerance;
+        let mut diff = SceneDiff::default();
+        let mut matched_after = vec![false; other.obstacles.len()];
+        let mut pairs: Vec<(usize, usize)> = vec![];
+
+        let mut repeated: HashSet<&str> = HashSet::new();
+        for scene in [self, other] {
+            let mut seen = HashSet::new();
+            for id in scene
+                .obstacles
+                .iter()
+                .filter_map(|b| b.global_id.as_deref())
+            {
+                if !seen.insert(id) {
+                    repeated.insert(id);
+                }
+            }
+        }
+        let ids: HashMap<&str, usize> = other
+            .obstacles
+            .iter()
+            .enumerate()
+            .filter_map(|(i, bbox)| Some((trusted_id(bbox, &repeated)?, i)))
+            .collect();
+        let mut unmatched: Vec<usize> = vec![];
+        for (i, bbox) in self.obstacles.iter().enumerate() {
+            match trusted_id(bbox, &repeated) {
+                Some(id) => match ids.get(id) {
+                    Some(&j) => {
+                        matched_after[j] = true;
+                        pairs.push((i, j));
+                    }
+                    None => diff.elements.push(ElementDiff::Removed { before: i }),
+                },
+                None => unmatched.push(i),
+            }
+        }
+
+        // Elements without an id, grouped by class.
+        let mut candidates: HashMap<Option<&str>, Vec<usize>> = HashMap::new();
+        for (j, bbox) in other.obstacles.iter().enumerate() {
+            if trusted_id(bbox, &repeated).is_none() {
+                candidates.entry(bbox.class.as_deref()).or_default().push(j);
+            }
+        }
+
+        let mut remaining = vec![];
+        for i in unmatched {
+            let bounds = &self.obstacles[i].bounds;
+            let exact = candidates
+                .get(&self.obstacles[i].class.as_deref())
+                .and_then(|group| {
+                    group.iter().copied().find(|&j| {
+                        !matched_after[j] && close(bounds, &other.obstacles[j].bounds, tolerance)
+                    })
+                });
+            match exact {
+                Some(j) => {
+                    matched_after[j] = true;
+                    pairs.push((i, j));
+                }
+                None => remaining.push(i),
+            }
+        }
+
+        // Closest pairs first, so a cluster of similar elements is not matched crosswise.
+        let mut near: Vec<(f32, usize, usize)> = vec![];
+        for &i in remaining.iter() {
+            let a = &self.obstacles[i].bounds;
+            let Some(group) = candidates.get(&self.obstacles[i].class.as_deref()) else {
+                continue;
+            };
+            for &j in group.iter().filter(|&&j| !matched_after[j]) {
+                let b = &other.obstacles[j].bounds;
+                let distance = (b.midpoint() - a.midpoint()).length();
+                let moved = same_size(a, b, tolerance) && distance <= config.max_move;
+                if moved || a.intersection(b).is_some() {
+                    near.push((distance, i, j));
+                }
+            }
+        }
+        near.sort_by(|a, b| a.0.total_cmp(&b.0));
+        let mut matched_before = vec![false; self.obstacles.len()];
+        for (_, i, j) in near {
+            if !matched_before[i] && !matched_after[j] {
+                matched_before[i] = true;
+                matched_after[j] = true;
+                pairs.push((i, j));
+            }
+        }
+        for i in remaining {
+            if !matched_before[i] {
+                diff.elements.push(ElementDiff::Removed { before: i });
+            }
+        }
+
+        for (i, j) in pairs {
+            match compare(i, &self.obstacles[i], j, &other.obstacles[j], tolerance) {
+                Some(change) => diff.elements.push(change),
+                None => diff.unchanged += 1,
+            }
+        }
+        for (j, matched) in matched_after.iter().enumerate() {
+            if !matched {
+                diff.elements.push(ElementDiff::Added { after: j });
+            }
+        }
+        diff
+    }
+}
@@ -0,0 +1,181 @@
+use crate::math::vector::Vector3;
+
+use super::{BBox, Bounds, Scene};
+
+#[derive(Debug, Clone)]
+pub enum SceneEdit {
+    Add(BBox),
+    Remove(usize),
+    Move {
+        element: usize,
+        offset: Vector3,
+    },
+    Resize {
+        element: usize,
+        bounds: Bounds,
+    },
+    SetClass {
+        element: usize,
+        class: Option<String>,
+    },
+    SetPenetrable {
+        element: usize,
+        penetrable: Option<bool>,
+    },
+}
+
+// One element before and after an edit: added elements have no `before`, removed ones no
+// `after`. Adding or removing shifts the indices of every later element by one.
+#[derive(Debug, Clone)]
+pub struct SceneChange {
+    pub element: usize,
+    pub before: Option<BBox>,
+    pub after: Option<BBox>,
+}
+
+impl SceneChange {
+    // Space whose contents may differ after the change.
+    pub fn dirty_region(&self) -> Bounds {
+        [&self.before, &self.after]
+            .into_iter()
+            .flatten()
+            .map(|bbox| bbox.bounds)
+            .into()
+    }
+
+    // Whether routing may see a difference. Besides obstacles, elements such as spaces steer
+    // routes through zones and constraints by their bounds, class or name, so any of those
+    // changing counts, as does penetrability.
+    pub fn affects_routing(&self) -> bool {
+        let key = |bbox: &Option<BBox>| {
+            bbox.as_ref().map(|bbox| {
+                let (min, max) = (bbox.bounds.min, bbox.bounds.max);
+                (
+                    [min.x, min.y, min.z, max.x, max.y, max.z],
+                    bbox.class.clone(),
+                    bbox.name.clone(),
+                    bbox.penetrable,
+                )
+            })
+        };
+        key(&self.before) != key(&self.after)
+    }
+
+    // Whether the element ends up outside `bounds`, beyond the reach of structures built over
+    // them.
+    pub fn leaves(&self, bounds: &Bounds) -> bool {
+        self.after.as_ref().is_some_and(|bbox| {
+            let (min, max) = (bbox.bounds.min, bbox.bounds.max);
+            min.x < bounds.min.x
+                || min.y < bounds.min.y
+                || min.z < bounds.min.z
+                || max.x > bounds.max.x
+                || max.y > bounds.max.y
+                || max.z > bounds.max.z
+        })
+    }
+
+    pub fn inverse(&self) -> SceneChange {
+        SceneChange {
+            element: self.element,
+            before: self.after.clone(),
+            after: self.before.clone(),
+        }
+    }
+}
+
+impl Scene {
+    // Applies an edit, returning the change it made, or `None` for an unknown element.
+    pub fn edit(&mut self, edit: SceneEdit) -> Option<SceneChange> {
+        let modify = |scene: &Scene, element: usize, f: &dyn Fn(&mut BBox)| {
+            let before = scene.obstacles.get(element)?.clone();
+            let mut after = before.clone();
+            f(&mut after);
+            Some(SceneChange {
+                element,
+                before: Some(before),
+                after: Some(after),
+            })
+        };
+        let change = match edit {
+            SceneEdit::Add(bbox) => SceneChange {
+                element: self.obstacles.len(),
+                before: None,
+                after: Some(bbox),
+            },
+            SceneEdit::Remove(element) => SceneChange {
+                element,
+                before: Some(self.obstacles.get(element)?.clone()),
+                after: None,
+            },
+            SceneEdit::Move { element, offset } => modify(self, element, &|bbox| {
+                bbox.bounds = Bounds::new(bbox.bounds.min + offset, bbox.bounds.max + offset);
+            })?,
+            SceneEdit::Resize { element, bounds } => {
+                modify(self, element, &|bbox| bbox.bounds = bounds)?
+            }
+            SceneEdit::SetClass { element, class } => {
+                modify(self, element, &|bbox| bbox.class = class.clone())?
+            }
+            SceneEdit::SetPenetrable {
+                element,
+                penetrable,
+            } => modify(self, element, &|bbox| bbox.penetrable = penetrable)?,
+        };
+        self.apply_change(&change);
+        Some(change)
+    }
+
+    // Brings the element named by `change` into its `after` state.
+    pub fn apply_change(&mut self, change: &SceneChange) {
+        match (&change.before, &change.after) {
+            (None, Some(bbox)) => self.obstacles.insert(change.element, bbox.clone()),
+            (Some(_), None) => {
+                self.obstacles.remove(change.element);
+            }
+            (Some(_), Some(bbox)) => self.obstacles[change.element] = bbox.clone(),
+            (None, None) => {}
+        }
+        self.bounds = self.obstacles.iter().map(|o| o.bounds).into();
+    }
+}
+
+// Undo and redo stacks of applied changes.
+#[derive(Debug, Default)]
+pub struct EditHistory {
+    done: Vec<SceneChange>,
+    undone: Vec<SceneChange>,
+}
+
+impl EditHistory {
+    pub fn edit(&mut self, scene: &mut Scene, edit: SceneEdit) -> Option<SceneChange> {
+        let change = scene.edit(edit)?;
+        self.done.push(change.clone());
+        self.undone.clear();
+        Some(change)
+    }
+
+    // Reverts the last edit, returning the change that did so.
+    pub fn undo(&mut self, scene: &mut Scene) -> Option<SceneChange> {
+        let change = self.done.pop()?;
+        let inverse = change.inverse();
+        scene.apply_change(&inverse);
+        self.undone.push(change);
+        Some(inverse)
+    }
+
+    pub fn redo(&mut self, scene: &mut Scene) -> Option<SceneChange> {
+        let change = self.undone.pop()?;
+        scene.apply_change(&change);
+        self.done.push(change.clone());
+        Some(change)
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.done.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.undone.is_empty()
+    }
+}
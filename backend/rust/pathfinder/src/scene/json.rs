@@ -27,6 +27,9 @@ pub(super) struct JsonBBox {
     pub yDist: f32,
     pub zDist: f32,
     pub r#type: Option<String>,
+    #[serde(rename = "globalId")]
+    pub global_id: Option<String>,
+    pub name: Option<String>,
     pub penetrable: Option<bool>,
 }
 